@@ -0,0 +1,44 @@
+// Instructions per second from calling tick, against run_ticks and its dispatch cache.
+//
+// Run with `cargo bench --no-default-features`. tests/dispatch.rs checks both ways do the
+// same thing.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use yachip8emu::asm;
+use yachip8emu::cpu::{Emu, Platform};
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::{build, ARITHMETIC, PROGRAM};
+
+const TICKS: u64 = 100_000;
+
+fn tick(emu: &mut Emu, ticks: u64) {
+    for _ in 0..ticks {
+        emu.tick();
+    }
+}
+
+fn bench_program(c: &mut Criterion, name: &str, source: &str) {
+    let rom = asm::assemble(source).unwrap();
+
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Elements(TICKS));
+    group.bench_function("tick", |b| {
+        b.iter(|| tick(black_box(&mut build(&rom, Platform::Chip8)), TICKS));
+    });
+    group.bench_function("run_ticks", |b| {
+        b.iter(|| black_box(&mut build(&rom, Platform::Chip8)).run_ticks(TICKS as u32));
+    });
+    group.finish();
+}
+
+fn dispatch(c: &mut Criterion) {
+    bench_program(c, "mixed", PROGRAM);
+    bench_program(c, "arithmetic", ARITHMETIC);
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
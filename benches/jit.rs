@@ -0,0 +1,46 @@
+// Instructions per second from the interpreter against compiled blocks.
+//
+// Run with `cargo bench --no-default-features --features jit`. tests/jit.rs checks both ways
+// do the same thing.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use yachip8emu::asm;
+use yachip8emu::cpu::Platform;
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::{build, ARITHMETIC, PROGRAM};
+
+const TICKS: u64 = 100_000;
+
+fn bench_program(c: &mut Criterion, name: &str, source: &str) {
+    let rom = asm::assemble(source).unwrap();
+
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Elements(TICKS));
+    // Both programs loop forever, so the machines keep running where they left off and
+    // everything has been decoded or compiled after the first iteration
+    let mut emu = build(&rom, Platform::Chip8);
+    group.bench_function("run_ticks", |b| {
+        b.iter(|| black_box(&mut emu).run_ticks(TICKS as u32));
+    });
+    let mut emu = build(&rom, Platform::Chip8);
+    group.bench_function("run_jit", |b| {
+        b.iter(|| black_box(&mut emu).run_jit(TICKS as u32));
+    });
+    // Starting from scratch each time, which counts setting up Cranelift and compiling
+    group.bench_function("run_jit_cold", |b| {
+        b.iter(|| black_box(&mut build(&rom, Platform::Chip8)).run_jit(TICKS as u32));
+    });
+    group.finish();
+}
+
+fn jit(c: &mut Criterion) {
+    bench_program(c, "mixed", PROGRAM);
+    bench_program(c, "arithmetic", ARITHMETIC);
+}
+
+criterion_group!(benches, jit);
+criterion_main!(benches);
@@ -0,0 +1,191 @@
+// Command line options for how the machine runs, shared by the window and terminal frontends
+
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+
+use clap::{Args, ValueEnum};
+
+use crate::cpu::Emu;
+use crate::cpu::Platform;
+use crate::cpu::START_ADDR;
+use crate::cpu::VIP_STACK_DEPTH;
+use crate::font::Font;
+use crate::font::FontSet;
+use crate::font::FONT_SIZE;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum QuirkPreset {
+    /// Behaviour most modern interpreters and games expect
+    Modern,
+    /// Original COSMAC VIP behaviour for shifts, BNNN and FX55/FX65
+    #[value(alias = "cosmac")]
+    Vip,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PlatformName {
+    /// Hi-res CHIP-8 for ROMs starting with its 1260 jump, MegaChip-8 for ones starting with
+    /// 0011, otherwise plain CHIP-8
+    Auto,
+    /// Plain CHIP-8
+    Chip8,
+    /// The VIP's 64x64 hi-res CHIP-8, with the 1260 jump added if the ROM doesn't start with one
+    Hires,
+    /// CHIP-8X for the VIP's VP-590 colour board, with programs at 0x300
+    Chip8x,
+    /// MegaChip-8, which switches to a 256x192 colour screen with 0011
+    Megachip,
+    /// ETI-660, with programs at 0x600 and a 64x48 screen
+    Eti660,
+    /// DREAM 6800, with its own font and keypad layout
+    Dream6800,
+    /// CHIP-8E, with its extra skips, relative jumps and port I/O
+    Chip8e,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum FontName {
+    /// The platform's own font
+    Auto,
+    /// The font most modern interpreters use
+    Modern,
+    /// The COSMAC VIP's original digits
+    Vip,
+    /// The ETI-660's three pixel wide digits
+    Eti660,
+    /// The DREAM 6800's three pixel wide digits
+    Dream6800,
+    /// SCHIP's 10-row big digits with the modern small ones
+    Schip,
+    /// Octo's big digits, including A-F, with the modern small ones
+    Octo,
+}
+
+// Which machine a ROM is for, which decides where it's loaded and what its instructions mean
+#[derive(Args, Clone)]
+pub struct PlatformArgs {
+    /// Machine and instruction set to emulate
+    #[arg(long = "platform", value_enum, default_value_t = PlatformName::Auto)]
+    pub name: PlatformName,
+}
+
+impl PlatformArgs {
+    // Auto looks at the start of the ROM
+    pub fn resolve(&self, rom: &[u8]) -> Platform {
+        match self.name {
+            PlatformName::Auto => Platform::detect(rom),
+            PlatformName::Chip8 => Platform::Chip8,
+            PlatformName::Hires => Platform::Chip8Hires,
+            PlatformName::Chip8x => Platform::Chip8X,
+            PlatformName::Megachip => Platform::MegaChip,
+            PlatformName::Eti660 => Platform::Eti660,
+            PlatformName::Dream6800 => Platform::Dream6800,
+            PlatformName::Chip8e => Platform::Chip8E,
+        }
+    }
+}
+
+// Options that affect how the machine itself runs
+#[derive(Args, Clone)]
+pub struct MachineArgs {
+    /// Quirk preset to emulate
+    #[arg(short, long, value_enum, default_value_t = QuirkPreset::Modern)]
+    pub quirks: QuirkPreset,
+    #[command(flatten)]
+    pub platform: PlatformArgs,
+    /// Instructions executed per second
+    #[arg(long, default_value_t = 600, value_parser = clap::value_parser!(u32).range(1..))]
+    pub ips: u32,
+    /// Built-in font for FX29 and FX30
+    #[arg(long, value_enum, default_value_t = FontName::Auto)]
+    pub font: FontName,
+    /// Font file of 80 bytes of small digits, optionally followed by 160 bytes of big ones
+    #[arg(long, value_name = "FILE", conflicts_with = "font")]
+    pub font_file: Option<PathBuf>,
+    /// Address of the font in memory, in hex (some interpreters use 50)
+    #[arg(long, value_name = "ADDR", value_parser = parse_font_addr)]
+    pub font_addr: Option<u16>,
+    /// Run at a COSMAC VIP's real speed, timing each instruction in machine cycles (ignores --ips)
+    #[arg(long)]
+    pub vip_timing: bool,
+    /// Nested calls allowed before the stack overflows [default: 12 with --quirks vip or
+    /// on the VIP's hires, chip8x and chip8e, otherwise 16]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(1..=16))]
+    pub stack_depth: Option<u8>,
+    /// Seed for the CXNN random number generator
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Log every executed instruction to a file, or - for stdout
+    #[arg(long, value_name = "FILE")]
+    pub trace: Option<PathBuf>,
+}
+
+// Create an emulator set up the way the command line asked, with the ROM loaded
+pub fn build_emu(machine: &MachineArgs, rom: &[u8]) -> Result<Emu, String> {
+    let mut chip8_inst = Emu::new();
+
+    chip8_inst.set_platform(machine.platform.resolve(rom));
+
+    if let Some(addr) = machine.font_addr {
+        chip8_inst.set_font_addr(addr).map_err(|e| e.to_string())?;
+    }
+    let font = match &machine.font_file {
+        Some(path) => Some(Font::load(path)?),
+        None => match machine.font {
+            FontName::Auto => None,
+            FontName::Modern => Some(FontSet::Modern),
+            FontName::Vip => Some(FontSet::Vip),
+            FontName::Eti660 => Some(FontSet::Eti660),
+            FontName::Dream6800 => Some(FontSet::Dream6800),
+            FontName::Schip => Some(FontSet::Schip),
+            FontName::Octo => Some(FontSet::Octo),
+        }.map(Font::builtin),
+    };
+    chip8_inst.set_font(font);
+
+    if let QuirkPreset::Vip = machine.quirks {
+        chip8_inst.set_cosmac();
+    }
+
+    // Otherwise each platform has its own
+    let depth = match machine.quirks {
+        QuirkPreset::Vip => Some(VIP_STACK_DEPTH),
+        QuirkPreset::Modern => None,
+    };
+    if let Some(depth) = machine.stack_depth.map(|depth| depth as usize).or(depth) {
+        chip8_inst.set_stack_depth(depth);
+    }
+
+    if machine.vip_timing {
+        chip8_inst.set_vip_timing();
+    }
+
+    if let Some(seed) = machine.seed {
+        chip8_inst.set_seed(seed);
+    }
+
+    if let Some(path) = &machine.trace {
+        if path.as_os_str() == "-" {
+            chip8_inst.set_trace(Some(Box::new(io::stdout())));
+        }
+        else {
+            let file = File::create(path)
+                .map_err(|e| format!("couldn't create trace file {}: {}", path.display(), e))?;
+            chip8_inst.set_trace(Some(Box::new(io::BufWriter::new(file))));
+        }
+    }
+
+    chip8_inst.load(rom).map_err(|e| e.to_string())?;
+
+    Ok(chip8_inst)
+}
+
+// Every platform's programs start at 0x200 or later, so the font has to end by then
+fn parse_font_addr(text: &str) -> Result<u16, String> {
+    let last = START_ADDR as usize - FONT_SIZE;
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+        .filter(|addr| *addr as usize <= last)
+        .ok_or(format!("'{}' isn't a hex address up to {:X}, leaving room for the font before programs start", text, last))
+}
@@ -0,0 +1,276 @@
+// A small two-pass assembler for the mnemonics produced by the disassembler.
+//
+// Each line holds an optional `label:`, then an instruction or a `DB`/`DW` data
+// directive. Anything after a `;` is a comment. Numbers may be decimal, `0x` hex
+// or `0b` binary, and anywhere a number is expected a label can be used instead.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::cpu::Platform;
+
+#[derive(Debug, PartialEq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Clone, PartialEq)]
+enum Operand {
+    V(u16),
+    I,
+    IndirectI,
+    DT,
+    ST,
+    K,
+    F,
+    HF,
+    B,
+    // A number or a label, resolved in the second pass
+    Value(String),
+}
+
+struct Statement {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<Operand>,
+}
+
+impl Statement {
+    // Bytes this statement takes up in the ROM
+    fn size(&self) -> usize {
+        match self.mnemonic.as_str() {
+            "DB" => self.operands.len(),
+            "DW" => self.operands.len() * 2,
+            _ => 2,
+        }
+    }
+}
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    assemble_for(source, Platform::Chip8)
+}
+
+// Labels count from where the platform loads programs, and its own instructions are allowed
+pub fn assemble_for(source: &str, platform: Platform) -> Result<Vec<u8>, AsmError> {
+    let mut statements = Vec::new();
+    let mut labels = HashMap::new();
+    let mut addr = platform.start_addr() as usize;
+
+    // First pass: split lines up and work out where every label lands
+    for (i, raw) in source.lines().enumerate() {
+        let line = i + 1;
+        let mut text = raw.split(';').next().unwrap().trim();
+
+        if let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if label.is_empty() || label.contains(char::is_whitespace) {
+                return Err(error(line, format!("invalid label '{}'", label)));
+            }
+            if labels.insert(label.to_string(), addr).is_some() {
+                return Err(error(line, format!("label '{}' is defined twice", label)));
+            }
+            text = text[colon + 1..].trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, rest) = match text.find(char::is_whitespace) {
+            Some(space) => (&text[..space], text[space..].trim()),
+            None => (text, ""),
+        };
+        let operands = if rest.is_empty() {
+            Vec::new()
+        }
+        else {
+            rest.split(',').map(|op| parse_operand(op.trim())).collect()
+        };
+
+        let statement = Statement { line, mnemonic: mnemonic.to_uppercase(), operands };
+        addr += statement.size();
+        statements.push(statement);
+    }
+
+    // Second pass: encode everything now that labels are known
+    let mut rom = Vec::new();
+    for statement in &statements {
+        match statement.mnemonic.as_str() {
+            "DB" => {
+                for op in &statement.operands {
+                    rom.push(resolve(op, &labels, 0xFF, statement.line)? as u8);
+                }
+            },
+            "DW" => {
+                for op in &statement.operands {
+                    let word = resolve(op, &labels, 0xFFFF, statement.line)?;
+                    rom.extend_from_slice(&word.to_be_bytes());
+                }
+            },
+            _ => {
+                let op = encode(statement, &labels, platform)?;
+                rom.extend_from_slice(&op.to_be_bytes());
+            },
+        }
+    }
+
+    Ok(rom)
+}
+
+fn error(line: usize, message: String) -> AsmError {
+    AsmError { line, message }
+}
+
+fn parse_operand(text: &str) -> Operand {
+    let upper = text.to_uppercase();
+    match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DT,
+        "ST" => Operand::ST,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "HF" => Operand::HF,
+        "B" => Operand::B,
+        _ => {
+            let reg = upper.strip_prefix('V').and_then(|n| u16::from_str_radix(n, 16).ok());
+            match reg {
+                Some(x) if upper.len() == 2 => Operand::V(x),
+                _ => Operand::Value(text.to_string()),
+            }
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    let lower = text.to_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    }
+    else if let Some(bin) = lower.strip_prefix("0b") {
+        u32::from_str_radix(bin, 2).ok()
+    }
+    else {
+        lower.parse::<u32>().ok()
+    }
+}
+
+fn resolve(op: &Operand, labels: &HashMap<String, usize>, max: u32, line: usize) -> Result<u16, AsmError> {
+    let text = match op {
+        Operand::Value(text) => text,
+        _ => return Err(error(line, String::from("expected a number or label"))),
+    };
+
+    let value = match parse_number(text) {
+        Some(n) => n,
+        None => match labels.get(text) {
+            Some(addr) => *addr as u32,
+            None => return Err(error(line, format!("unknown label '{}'", text))),
+        },
+    };
+
+    if value > max {
+        return Err(error(line, format!("{} doesn't fit in {:#X}", text, max)));
+    }
+
+    Ok(value as u16)
+}
+
+fn encode(statement: &Statement, labels: &HashMap<String, usize>, platform: Platform) -> Result<u16, AsmError> {
+    let line = statement.line;
+    let ops = statement.operands.as_slice();
+    let addr = |op: &Operand| resolve(op, labels, 0xFFF, line);
+    let byte = |op: &Operand| resolve(op, labels, 0xFF, line);
+    let nibble = |op: &Operand| resolve(op, labels, 0xF, line);
+
+    let chip8e = platform == Platform::Chip8E;
+    let chip8x = platform == Platform::Chip8X;
+    let megachip = platform == Platform::MegaChip;
+
+    let op = match (statement.mnemonic.as_str(), ops) {
+        ("HCLS", []) if platform == Platform::Chip8Hires => 0x0230,
+        ("STOP", []) if chip8e => 0x00ED,
+        ("NOP", []) if chip8e => 0x00F2,
+        ("WAIT", [Operand::DT]) if chip8e => 0x0151,
+        ("WAIT", [Operand::DT, Operand::V(x)]) if chip8e => 0xF04F | x << 8,
+        ("SKIP", []) if chip8e => 0x0188,
+        ("SKIP", [Operand::V(x)]) if chip8e => 0xF01B | x << 8,
+        ("SGT", [Operand::V(x), Operand::V(y)]) if chip8e => 0x5001 | x << 8 | y << 4,
+        ("STR", [Operand::V(x), Operand::V(y)]) if chip8e => 0x5002 | x << 8 | y << 4,
+        ("LDR", [Operand::V(x), Operand::V(y)]) if chip8e => 0x5003 | x << 8 | y << 4,
+        ("JB", [b]) if chip8e => 0xBB00 | byte(b)?,
+        ("JF", [b]) if chip8e => 0xBF00 | byte(b)?,
+        ("OUT", [Operand::V(x)]) if chip8e => 0xF003 | x << 8,
+        ("INS", [Operand::V(x)]) if chip8e => 0xF0E3 | x << 8,
+        ("IN", [Operand::V(x)]) if chip8e => 0xF0E7 | x << 8,
+        ("BGC", []) if chip8x => 0x02A0,
+        ("NADD", [Operand::V(x), Operand::V(y)]) if chip8x => 0x5001 | x << 8 | y << 4,
+        ("COL", [Operand::V(x), Operand::V(y), n]) if chip8x => 0xB000 | x << 8 | y << 4 | nibble(n)?,
+        ("SKP2", [Operand::V(x)]) if chip8x => 0xE0F2 | x << 8,
+        ("SKNP2", [Operand::V(x)]) if chip8x => 0xE0F5 | x << 8,
+        ("OUT", [Operand::V(x)]) if chip8x => 0xF0F8 | x << 8,
+        ("IN", [Operand::V(x)]) if chip8x => 0xF0FB | x << 8,
+        ("MEGAOFF", []) if megachip => 0x0010,
+        ("MEGAON", []) if megachip => 0x0011,
+        ("SCRU", [n]) if megachip => 0x00B0 | nibble(n)?,
+        ("LDHI", [b]) if megachip => 0x0100 | byte(b)?,
+        ("LDPAL", [b]) if megachip => 0x0200 | byte(b)?,
+        ("SPRW", [b]) if megachip => 0x0300 | byte(b)?,
+        ("SPRH", [b]) if megachip => 0x0400 | byte(b)?,
+        ("ALPHA", [b]) if megachip => 0x0500 | byte(b)?,
+        ("DIGISND", [n]) if megachip => 0x0600 | nibble(n)?,
+        ("STOPSND", []) if megachip => 0x0700,
+        ("BMODE", [n]) if megachip => 0x0800 | nibble(n)?,
+        ("CCOL", [b]) if megachip => 0x0900 | byte(b)?,
+        ("CLS", []) => 0x00E0,
+        ("RET", []) => 0x00EE,
+        ("SYS", [a]) => addr(a)?,
+        ("JP", [Operand::V(0), a]) => 0xB000 | addr(a)?,
+        ("JP", [a]) => 0x1000 | addr(a)?,
+        ("CALL", [a]) => 0x2000 | addr(a)?,
+        ("SE", [Operand::V(x), Operand::V(y)]) => 0x5000 | x << 8 | y << 4,
+        ("SE", [Operand::V(x), b]) => 0x3000 | x << 8 | byte(b)?,
+        ("SNE", [Operand::V(x), Operand::V(y)]) => 0x9000 | x << 8 | y << 4,
+        ("SNE", [Operand::V(x), b]) => 0x4000 | x << 8 | byte(b)?,
+        ("LD", [Operand::V(x), Operand::V(y)]) => 0x8000 | x << 8 | y << 4,
+        ("LD", [Operand::V(x), Operand::DT]) => 0xF007 | x << 8,
+        ("LD", [Operand::V(x), Operand::K]) => 0xF00A | x << 8,
+        ("LD", [Operand::V(x), Operand::IndirectI]) => 0xF065 | x << 8,
+        ("LD", [Operand::V(x), b]) => 0x6000 | x << 8 | byte(b)?,
+        ("LD", [Operand::I, a]) => 0xA000 | addr(a)?,
+        ("LD", [Operand::DT, Operand::V(x)]) => 0xF015 | x << 8,
+        ("LD", [Operand::ST, Operand::V(x)]) => 0xF018 | x << 8,
+        ("LD", [Operand::F, Operand::V(x)]) => 0xF029 | x << 8,
+        ("LD", [Operand::HF, Operand::V(x)]) => 0xF030 | x << 8,
+        ("LD", [Operand::B, Operand::V(x)]) => 0xF033 | x << 8,
+        ("LD", [Operand::IndirectI, Operand::V(x)]) => 0xF055 | x << 8,
+        ("ADD", [Operand::V(x), Operand::V(y)]) => 0x8004 | x << 8 | y << 4,
+        ("ADD", [Operand::V(x), b]) => 0x7000 | x << 8 | byte(b)?,
+        ("ADD", [Operand::I, Operand::V(x)]) => 0xF01E | x << 8,
+        ("OR", [Operand::V(x), Operand::V(y)]) => 0x8001 | x << 8 | y << 4,
+        ("AND", [Operand::V(x), Operand::V(y)]) => 0x8002 | x << 8 | y << 4,
+        ("XOR", [Operand::V(x), Operand::V(y)]) => 0x8003 | x << 8 | y << 4,
+        ("SUB", [Operand::V(x), Operand::V(y)]) => 0x8005 | x << 8 | y << 4,
+        ("SHR", [Operand::V(x)]) => 0x8006 | x << 8 | x << 4,
+        ("SHR", [Operand::V(x), Operand::V(y)]) => 0x8006 | x << 8 | y << 4,
+        ("SUBN", [Operand::V(x), Operand::V(y)]) => 0x8007 | x << 8 | y << 4,
+        ("SHL", [Operand::V(x)]) => 0x800E | x << 8 | x << 4,
+        ("SHL", [Operand::V(x), Operand::V(y)]) => 0x800E | x << 8 | y << 4,
+        ("RND", [Operand::V(x), b]) => 0xC000 | x << 8 | byte(b)?,
+        ("DRW", [Operand::V(x), Operand::V(y), n]) => 0xD000 | x << 8 | y << 4 | nibble(n)?,
+        ("SKP", [Operand::V(x)]) => 0xE09E | x << 8,
+        ("SKNP", [Operand::V(x)]) => 0xE0A1 | x << 8,
+        (mnemonic, _) => {
+            return Err(error(line, format!("can't assemble '{}' with these operands", mnemonic)));
+        }
+    };
+
+    Ok(op)
+}
@@ -0,0 +1,94 @@
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+const SAMPLE_RATE: i32 = 44100;
+const TONE_HZ: f32 = 440.0;
+const VOLUME: f32 = 0.1;
+
+// The square wave beep, with MegaChip's sampled sound mixed on top
+pub struct Speaker {
+    sample_rate: f32,
+    phase_inc: f32,
+    phase: f32,
+    beeping: bool,
+    // Centred on 0, empty when nothing is playing
+    samples: Vec<f32>,
+    sample_pos: f32,
+    sample_step: f32,
+    looping: bool,
+}
+
+impl Speaker {
+    // Play hz, or the usual tone for None
+    pub fn set_tone(&mut self, hz: Option<f32>) {
+        self.phase_inc = hz.unwrap_or(TONE_HZ) / self.sample_rate;
+    }
+
+    pub fn set_beeping(&mut self, beeping: bool) {
+        self.beeping = beeping;
+    }
+
+    // Unsigned 8-bit samples recorded at rate Hz
+    pub fn play_sample(&mut self, samples: &[u8], rate: u32, looping: bool) {
+        self.samples = samples.iter().map(|s| (*s as f32 - 128.0) / 128.0).collect();
+        self.sample_pos = 0.0;
+        self.sample_step = rate as f32 / self.sample_rate;
+        self.looping = looping;
+    }
+
+    pub fn stop_sample(&mut self) {
+        self.samples.clear();
+    }
+
+    // Whether there's anything to hear
+    pub fn is_playing(&self) -> bool {
+        self.beeping || !self.samples.is_empty()
+    }
+}
+
+impl AudioCallback for Speaker {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = 0.0;
+            if self.beeping {
+                *sample += if self.phase < 0.5 { VOLUME } else { -VOLUME };
+            }
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+
+            if !self.samples.is_empty() {
+                *sample += self.samples[self.sample_pos as usize] * VOLUME;
+                self.sample_pos += self.sample_step;
+                if self.sample_pos as usize >= self.samples.len() {
+                    if self.looping {
+                        self.sample_pos %= self.samples.len() as f32;
+                    }
+                    else {
+                        self.samples.clear();
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The device starts paused, resume it while there's something to play
+pub fn open_beeper(audio_subsys: &AudioSubsystem) -> Result<AudioDevice<Speaker>, String> {
+    let spec = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE),
+        channels: Some(1),
+        samples: None,
+    };
+
+    audio_subsys.open_playback(None, &spec, |spec| Speaker {
+        sample_rate: spec.freq as f32,
+        phase_inc: TONE_HZ / spec.freq as f32,
+        phase: 0.0,
+        beeping: false,
+        samples: Vec::new(),
+        sample_pos: 0.0,
+        sample_step: 0.0,
+        looping: false,
+    })
+}
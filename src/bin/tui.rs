@@ -0,0 +1,303 @@
+use std::fs;
+use std::io::{self, Stdout, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+
+use crossterm::cursor;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::event::{KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags};
+use crossterm::execute;
+use crossterm::queue;
+use crossterm::style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+
+use yachip8emu::args::build_emu;
+use yachip8emu::args::MachineArgs;
+use yachip8emu::cpu::Emu;
+use yachip8emu::keymap::Keymap;
+
+const FPS: f64 = 60.0;
+const FRAME_TIME: Duration = Duration::from_micros(16_667);
+// Most terminals only report presses, so a key stays held until it stops repeating
+const KEY_RELEASE_TIMEOUT: Duration = Duration::from_millis(150);
+const BG_COLOR: Color = Color::Rgb { r: 84, g: 49, b: 163 };
+const FG_COLOR: Color = Color::Rgb { r: 123, g: 156, b: 237 };
+const NUM_KEYS: usize = 16;
+
+#[derive(Parser)]
+#[command(name = "tui", version, about = "Play a CHIP-8 ROM in the terminal")]
+struct Args {
+    rom: PathBuf,
+    #[command(flatten)]
+    machine: MachineArgs,
+    /// Draw with braille characters instead of half blocks, which fits big screens in small terminals
+    #[arg(short, long)]
+    braille: bool,
+    /// File of `key = hex digit` lines replacing the default key bindings
+    #[arg(long, value_name = "FILE")]
+    keymap: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Glyphs {
+    // One cell covers 1x2 pixels, drawn with foreground and background colours
+    HalfBlock,
+    // One cell covers 2x4 pixels, so big screens fit in small terminals
+    Braille,
+}
+
+impl Glyphs {
+    fn cell_size(&self) -> (usize, usize) {
+        match self {
+            Glyphs::HalfBlock => (1, 2),
+            Glyphs::Braille => (2, 4),
+        }
+    }
+}
+
+// Puts the terminal back the way we found it, even if the emulator panics
+struct TerminalGuard {
+    enhanced_keys: bool,
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        if self.enhanced_keys {
+            let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(stdout, ResetColor, cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn main() -> io::Result<()> {
+    let args = Args::parse();
+    let glyphs = if args.braille { Glyphs::Braille } else { Glyphs::HalfBlock };
+
+    let (mut chip8_inst, keymap) = match setup(&args) {
+        Ok(setup) => setup,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return Ok(());
+        }
+    };
+
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(stdout, EnterAlternateScreen, cursor::Hide, terminal::Clear(ClearType::All))?;
+
+    // Terminals that can report key releases don't need the timeout
+    let enhanced_keys = terminal::supports_keyboard_enhancement().unwrap_or(false);
+    if enhanced_keys {
+        execute!(stdout, PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::REPORT_EVENT_TYPES))?;
+    }
+    let _guard = TerminalGuard { enhanced_keys };
+
+    // When each key on both keypads was last pressed
+    let mut held: [[Option<Instant>; NUM_KEYS]; 2] = [[None; NUM_KEYS]; 2];
+    let mut next_frame = Instant::now();
+    let ticks_per_frame = args.machine.ips as f64 / FPS;
+    let mut tick_budget = 0.0;
+
+    'gameloop: loop {
+        let mut redraw = false;
+
+        while event::poll(next_frame.saturating_duration_since(Instant::now()))? {
+            match event::read()? {
+                Event::Key(key) => match key.code {
+                    KeyCode::Esc => break 'gameloop,
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break 'gameloop,
+                    code => {
+                        // Shift only changes the case, not which key it is
+                        let code = match code {
+                            KeyCode::Char(c) => KeyCode::Char(c.to_ascii_lowercase()),
+                            code => code,
+                        };
+                        let pressed = key.kind != KeyEventKind::Release;
+                        if let Some(k) = keymap.get(code) {
+                            chip8_inst.keypress(k, pressed);
+                            held[0][k] = pressed.then(Instant::now);
+                        }
+                        if let Some(k) = keymap.get2(code) {
+                            chip8_inst.keypress2(k, pressed);
+                            held[1][k] = pressed.then(Instant::now);
+                        }
+                    }
+                },
+                // Resizing can clear or scramble what's on screen
+                Event::Resize(..) => redraw = true,
+                _ => (),
+            }
+        }
+        next_frame += FRAME_TIME;
+
+        if !enhanced_keys {
+            for (pad, keys) in held.iter_mut().enumerate() {
+                for (k, pressed_at) in keys.iter_mut().enumerate() {
+                    if let Some(time) = pressed_at {
+                        if time.elapsed() > KEY_RELEASE_TIMEOUT {
+                            if pad == 0 {
+                                chip8_inst.keypress(k, false);
+                            }
+                            else {
+                                chip8_inst.keypress2(k, false);
+                            }
+                            *pressed_at = None;
+                        }
+                    }
+                }
+            }
+        }
+
+        if args.machine.vip_timing {
+            chip8_inst.run_frame();
+        }
+        else {
+            tick_budget += ticks_per_frame;
+            while tick_budget >= 1.0 {
+                chip8_inst.tick();
+                tick_budget -= 1.0;
+            }
+            chip8_inst.tick_timers();
+        }
+
+        let dirty = chip8_inst.take_dirty();
+        if redraw {
+            queue!(stdout, ResetColor, terminal::Clear(ClearType::All))?;
+            let (_, screen_h) = chip8_inst.get_screen_size();
+            draw_screen(&mut stdout, &chip8_inst, glyphs, 0..screen_h.div_ceil(glyphs.cell_size().1))?;
+        }
+        else if let Some(area) = dirty {
+            let (_, cell_h) = glyphs.cell_size();
+            let first_row = area.y / cell_h;
+            let last_row = (area.y + area.height).div_ceil(cell_h);
+            draw_screen(&mut stdout, &chip8_inst, glyphs, first_row..last_row)?;
+        }
+        draw_sidebar(&mut stdout, &chip8_inst, glyphs)?;
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
+
+// Build the machine and pick its keys before the terminal is taken over, so errors can be printed
+fn setup(args: &Args) -> Result<(Emu, Keymap<KeyCode>), String> {
+    // The trace would be drawn over the screen
+    if args.machine.trace.as_ref().is_some_and(|path| path.as_os_str() == "-") {
+        return Err(String::from("the terminal frontend can only trace to a file"));
+    }
+
+    let rom = fs::read(&args.rom).map_err(|e| format!("couldn't read {}: {}", args.rom.display(), e))?;
+    let chip8_inst = build_emu(&args.machine, &rom)?;
+    let keymap = match &args.keymap {
+        Some(path) => Keymap::load(path, key_from_name, &[KeyCode::Esc])?,
+        None => Keymap::for_platform(chip8_inst.get_platform(), key_from_name),
+    };
+
+    Ok((chip8_inst, keymap))
+}
+
+// Redraw the given range of terminal rows from the framebuffer
+fn draw_screen(
+    stdout: &mut Stdout,
+    emu: &Emu,
+    glyphs: Glyphs,
+    rows: std::ops::Range<usize>,
+) -> io::Result<()> {
+    let (screen_w, screen_h) = emu.get_screen_size();
+    let (cell_w, cell_h) = glyphs.cell_size();
+    let pixel = |x: usize, y: usize| y < screen_h && emu.get_pixel(x, y);
+
+    for row in rows {
+        queue!(stdout, cursor::MoveTo(0, row as u16))?;
+
+        for col in 0..screen_w / cell_w {
+            let x = col * cell_w;
+            let y = row * cell_h;
+
+            match glyphs {
+                Glyphs::HalfBlock => {
+                    let top = if pixel(x, y) { FG_COLOR } else { BG_COLOR };
+                    let bottom = if pixel(x, y + 1) { FG_COLOR } else { BG_COLOR };
+                    queue!(stdout, SetForegroundColor(top), SetBackgroundColor(bottom), Print('\u{2580}'))?;
+                },
+                Glyphs::Braille => {
+                    // Bit layout of the eight dots in a braille cell
+                    const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+                    let mut bits = 0;
+                    for (dx, column) in DOTS.iter().enumerate() {
+                        for (dy, dot) in column.iter().enumerate() {
+                            if pixel(x + dx, y + dy) {
+                                bits |= dot;
+                            }
+                        }
+                    }
+                    let glyph = char::from_u32(0x2800 + bits).unwrap();
+                    queue!(stdout, SetForegroundColor(FG_COLOR), SetBackgroundColor(BG_COLOR), Print(glyph))?;
+                },
+            }
+        }
+    }
+    queue!(stdout, ResetColor)?;
+
+    Ok(())
+}
+
+fn draw_sidebar(stdout: &mut Stdout, emu: &Emu, glyphs: Glyphs) -> io::Result<()> {
+    let (screen_w, _) = emu.get_screen_size();
+    let left = (screen_w / glyphs.cell_size().0 + 2) as u16;
+
+    let v_reg = emu.get_v_reg();
+    let (d_timer, s_timer) = emu.get_timers();
+
+    let mut lines = vec![
+        format!("PC {:#05X}", emu.get_pc()),
+        format!("I  {:#05X}", emu.get_i_reg()),
+        format!("DT {:02X}   ST {:02X}", d_timer, s_timer),
+        format!("SP {}/{}", emu.get_stack().len(), emu.get_stack_depth()),
+        String::new(),
+    ];
+    for i in (0..v_reg.len()).step_by(2) {
+        lines.push(format!("V{:X} {:02X}   V{:X} {:02X}", i, v_reg[i], i + 1, v_reg[i + 1]));
+    }
+    lines.push(String::new());
+    match emu.get_fault() {
+        Some(fault) => lines.push(format!("{}", fault)),
+        None => lines.push(String::new()),
+    }
+    lines.push(String::from("Esc: quit"));
+
+    for (i, line) in lines.iter().enumerate() {
+        queue!(stdout, cursor::MoveTo(left, i as u16), Print(format!("{:<16}", line)))?;
+    }
+
+    Ok(())
+}
+
+// Turn the SDL key names keymaps use into terminal keys. Terminals don't tell the numeric
+// keypad apart from the other keys, so its names aren't recognised
+fn key_from_name(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => return Some(KeyCode::Char(c.to_ascii_lowercase())),
+        (None, _) => return None,
+        _ => (),
+    }
+
+    match name.to_ascii_lowercase().as_str() {
+        "space" => Some(KeyCode::Char(' ')),
+        "up" => Some(KeyCode::Up),
+        "down" => Some(KeyCode::Down),
+        "left" => Some(KeyCode::Left),
+        "right" => Some(KeyCode::Right),
+        "return" => Some(KeyCode::Enter),
+        "tab" => Some(KeyCode::Tab),
+        "backspace" => Some(KeyCode::Backspace),
+        "escape" => Some(KeyCode::Esc),
+        _ => None,
+    }
+}
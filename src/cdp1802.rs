@@ -0,0 +1,468 @@
+// RCA CDP1802, the COSMAC VIP's processor, for running the machine code that
+// CHIP-8 programs call with 0NNN.
+//
+// Only what's needed for that is here: no DMA or interrupts, and of the VIP's
+// I/O just the keypad latch (OUT 2) and its key-down flag (EF3).
+
+// Machine cycles, 8 clock pulses each, for most instructions
+const SHORT_CYCLES: u32 = 2;
+// Long branches and skips take one more
+const LONG_CYCLES: u32 = 3;
+
+pub struct Cdp1802 {
+    // Scratchpad registers, any of which can be the program counter (P) or data pointer (X)
+    pub r: [u16; 16],
+    pub d: u8,
+    pub df: bool,
+    pub p: u8,
+    pub x: u8,
+    pub t: u8,
+    pub ie: bool,
+    pub q: bool,
+    // Keypad key selected by OUT 2, reported on EF3
+    pub key_latch: u8,
+}
+
+impl Default for Cdp1802 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cdp1802 {
+    // Registers as the chip comes out of reset
+    pub fn new() -> Self {
+        Cdp1802 {
+            r: [0; 16],
+            d: 0,
+            df: false,
+            p: 0,
+            x: 0,
+            t: 0,
+            ie: true,
+            q: false,
+            key_latch: 0,
+        }
+    }
+
+    // Run one instruction, returning how many machine cycles it took
+    pub fn step(&mut self, mem: &mut [u8], keys: &[bool]) -> u32 {
+        let op = self.fetch(mem);
+        let hi = op >> 4;
+        let n = (op & 0x0F) as usize;
+        let x = self.x as usize;
+
+        match (hi, n) {
+            // IDL: wait for an interrupt, which never comes here, so carry on
+            (0x0, 0) => (),
+            // LDN
+            (0x0, _) => self.d = read(mem, self.r[n]),
+            // INC
+            (0x1, _) => self.r[n] = self.r[n].wrapping_add(1),
+            // DEC
+            (0x2, _) => self.r[n] = self.r[n].wrapping_sub(1),
+            // Short branches
+            (0x3, _) => {
+                let taken = self.condition(n, keys);
+                let target = self.fetch(mem);
+                if taken {
+                    let pc = &mut self.r[self.p as usize];
+                    *pc = (*pc & 0xFF00) | target as u16;
+                }
+            },
+            // LDA
+            (0x4, _) => {
+                self.d = read(mem, self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            },
+            // STR
+            (0x5, _) => write(mem, self.r[n], self.d),
+            // IRX
+            (0x6, 0) => self.r[x] = self.r[x].wrapping_add(1),
+            // OUT 1-7: put M(R(X)) on the bus
+            (0x6, 1..=7) => {
+                let value = read(mem, self.r[x]);
+                if n == 2 {
+                    self.key_latch = value & 0x0F;
+                }
+                self.r[x] = self.r[x].wrapping_add(1);
+            },
+            // Not an instruction on the 1802
+            (0x6, 8) => (),
+            // INP 1-7: nothing drives the bus, so read zero
+            (0x6, _) => {
+                self.d = 0;
+                write(mem, self.r[x], 0);
+            },
+            (0x7, _) => self.execute_7(n, mem),
+            // GLO
+            (0x8, _) => self.d = self.r[n] as u8,
+            // GHI
+            (0x9, _) => self.d = (self.r[n] >> 8) as u8,
+            // PLO
+            (0xA, _) => self.r[n] = (self.r[n] & 0xFF00) | self.d as u16,
+            // PHI
+            (0xB, _) => self.r[n] = (self.r[n] & 0x00FF) | (self.d as u16) << 8,
+            // Long branches and skips
+            (0xC, _) => {
+                self.execute_long(n, mem);
+                return LONG_CYCLES;
+            },
+            // SEP
+            (0xD, _) => self.p = n as u8,
+            // SEX
+            (0xE, _) => self.x = n as u8,
+            // LDX
+            (0xF, 0) => self.d = read(mem, self.r[x]),
+            // Arithmetic and logic on M(R(X))
+            (0xF, 1..=7) => {
+                let value = read(mem, self.r[x]);
+                self.alu(n, value);
+            },
+            // LDI
+            (0xF, 8) => self.d = self.fetch(mem),
+            // SHL
+            (0xF, 0xE) => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            },
+            // ORI, ANI, XRI, ADI, SDI and SMI: the same operations on an immediate byte
+            _ => {
+                let value = self.fetch(mem);
+                self.alu(n - 8, value);
+            },
+        }
+
+        SHORT_CYCLES
+    }
+
+    // Read the byte at R(P) and step past it
+    fn fetch(&mut self, mem: &[u8]) -> u8 {
+        let pc = &mut self.r[self.p as usize];
+        let byte = read(mem, *pc);
+        *pc = pc.wrapping_add(1);
+        byte
+    }
+
+    // Branch condition for the low nibble of a short branch. 8-F are the inverses of 0-7
+    fn condition(&self, n: usize, keys: &[bool]) -> bool {
+        let flag = match n & 0x7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            // EF3 goes low while the latched key is held
+            6 => keys.get(self.key_latch as usize).copied().unwrap_or(false),
+            _ => false,
+        };
+        if n < 8 { flag } else { !flag }
+    }
+
+    fn execute_7(&mut self, n: usize, mem: &mut [u8]) {
+        let x = self.x as usize;
+
+        match n {
+            // RET and DIS: restore X and P from the stack
+            0x0 | 0x1 => {
+                let xp = read(mem, self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+                self.x = xp >> 4;
+                self.p = xp & 0x0F;
+                self.ie = n == 0x0;
+            },
+            // LDXA
+            0x2 => {
+                self.d = read(mem, self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+            },
+            // STXD
+            0x3 => {
+                write(mem, self.r[x], self.d);
+                self.r[x] = self.r[x].wrapping_sub(1);
+            },
+            // ADC, SDB, SMB
+            0x4 => self.add(read(mem, self.r[x]), self.df),
+            0x5 => self.subtract(read(mem, self.r[x]), self.d, self.df),
+            0x7 => self.subtract(self.d, read(mem, self.r[x]), self.df),
+            // SHRC: rotate right through DF
+            0x6 => {
+                let carry = self.d & 1 != 0;
+                self.d = (self.d >> 1) | (self.df as u8) << 7;
+                self.df = carry;
+            },
+            // SAV
+            0x8 => write(mem, self.r[x], self.t),
+            // MARK: save X and P, then push them on the R2 stack
+            0x9 => {
+                self.t = (self.x << 4) | self.p;
+                write(mem, self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            },
+            // REQ, SEQ
+            0xA => self.q = false,
+            0xB => self.q = true,
+            // ADCI, SDBI, SMBI
+            0xC => {
+                let value = self.fetch(mem);
+                self.add(value, self.df);
+            },
+            0xD => {
+                let value = self.fetch(mem);
+                self.subtract(value, self.d, self.df);
+            },
+            0xF => {
+                let value = self.fetch(mem);
+                self.subtract(self.d, value, self.df);
+            },
+            // SHLC: rotate left through DF
+            _ => {
+                let carry = self.d & 0x80 != 0;
+                self.d = (self.d << 1) | self.df as u8;
+                self.df = carry;
+            },
+        }
+    }
+
+    fn execute_long(&mut self, n: usize, mem: &[u8]) {
+        let p = self.p as usize;
+
+        // C4 is NOP, and C8 (LSKP) is the unconditional skip
+        let (taken, skip) = match n {
+            0x0 => (true, false),
+            0x1 => (self.q, false),
+            0x2 => (self.d == 0, false),
+            0x3 => (self.df, false),
+            0x4 => (false, true),
+            0x5 => (!self.q, true),
+            0x6 => (self.d != 0, true),
+            0x7 => (!self.df, true),
+            0x8 => (true, true),
+            0x9 => (!self.q, false),
+            0xA => (self.d != 0, false),
+            0xB => (!self.df, false),
+            0xC => (self.ie, true),
+            0xD => (self.q, true),
+            0xE => (self.d == 0, true),
+            _ => (self.df, true),
+        };
+
+        if skip {
+            if taken {
+                self.r[p] = self.r[p].wrapping_add(2);
+            }
+        }
+        else if taken {
+            let hi = read(mem, self.r[p]);
+            let lo = read(mem, self.r[p].wrapping_add(1));
+            self.r[p] = u16::from_be_bytes([hi, lo]);
+        }
+        else {
+            self.r[p] = self.r[p].wrapping_add(2);
+        }
+    }
+
+    // OR, AND, XOR, ADD, SD, SHR and SM, in the order of F1-F7
+    fn alu(&mut self, n: usize, value: u8) {
+        match n {
+            1 => self.d |= value,
+            2 => self.d &= value,
+            3 => self.d ^= value,
+            4 => self.add(value, false),
+            5 => self.subtract(value, self.d, true),
+            6 => {
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+            },
+            _ => self.subtract(self.d, value, true),
+        }
+    }
+
+    fn add(&mut self, value: u8, carry: bool) {
+        let sum = self.d as u16 + value as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    // D = a - b, with DF set when there's no borrow. no_borrow is the incoming DF for SDB/SMB
+    fn subtract(&mut self, a: u8, b: u8, no_borrow: bool) {
+        let diff = a as i16 - b as i16 - !no_borrow as i16;
+        self.d = diff as u8;
+        self.df = diff >= 0;
+    }
+}
+
+// The VIP decodes only as many address lines as it has memory, so addresses wrap around
+fn read(mem: &[u8], addr: u16) -> u8 {
+    mem[addr as usize % mem.len()]
+}
+
+fn write(mem: &mut [u8], addr: u16, value: u8) {
+    let len = mem.len();
+    mem[addr as usize % len] = value;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Where the VIP's interpreter keeps its stack when it calls machine code
+    const STACK: u16 = 0xECF;
+    const CODE: usize = 0x300;
+
+    // Run code the way a 0NNN call does, from 0x300 with P = 3 and X = 2, until it hands
+    // back to the interpreter with SEP R4
+    fn run(code: &[u8]) -> (Cdp1802, Vec<u8>) {
+        let mut mem = vec![0; 4096];
+        mem[CODE..CODE + code.len()].copy_from_slice(code);
+        let mut cpu = Cdp1802::new();
+        cpu.r[2] = STACK;
+        cpu.x = 2;
+        cpu.r[3] = CODE as u16;
+        cpu.p = 3;
+
+        let mut steps = 0;
+        while cpu.p != 4 {
+            assert!(steps < 1000, "never returned");
+            cpu.step(&mut mem, &[false; 16]);
+            steps += 1;
+        }
+        (cpu, mem)
+    }
+
+    // Put m at 0x400 for X to point at, then apply op to d and store the result back there
+    fn alu(d: u8, op: &[u8], m: u8) -> (Cdp1802, Vec<u8>) {
+        let mut code = vec![0xF8, 0x04, 0xB9, 0xF8, 0x00, 0xA9, 0xE9, 0xF8, m, 0x59, 0xF8, d];
+        code.extend_from_slice(op);
+        code.extend_from_slice(&[0x59, 0xD4]);
+        run(&code)
+    }
+
+    #[test]
+    fn alu_ops() {
+        for (d, op, m, result, df) in [
+            (0x3C, &[0xF1][..], 0xF0, 0xFC, false),
+            (0x3C, &[0xF2], 0xF0, 0x30, false),
+            (0x3C, &[0xF3], 0xF0, 0xCC, false),
+            (0x3C, &[0xF4], 0xF0, 0x2C, true),
+            (0x3C, &[0xF5], 0xF0, 0xB4, true),
+            (0x3D, &[0xF6], 0xF0, 0x1E, true),
+            (0x3C, &[0xF7], 0xF0, 0x4C, false),
+            (0x81, &[0xFE], 0x00, 0x02, true),
+            (0x3C, &[0xFC, 0xF0], 0x00, 0x2C, true),
+            (0x3C, &[0xFF, 0x3D], 0x00, 0xFF, false),
+            // DF starts clear, so these carry nothing in and borrow one
+            (0x3C, &[0x74], 0xF0, 0x2C, true),
+            (0x3C, &[0x77], 0x0F, 0x2C, true),
+            (0x3D, &[0x76], 0x00, 0x1E, true),
+            (0x81, &[0x7E], 0x00, 0x02, true),
+        ] {
+            let (cpu, mem) = alu(d, op, m);
+            assert_eq!((cpu.d, cpu.df), (result, df), "{:02X?} on {:02X} and {:02X}", op, d, m);
+            assert_eq!(mem[0x400], result);
+        }
+    }
+
+    #[test]
+    fn adc_carries_from_add() {
+        let (cpu, mem) = alu(0x3C, &[0xF4, 0x74], 0xF0);
+        assert_eq!((cpu.d, cpu.df), (0x1D, true));
+        assert_eq!(mem[0x400], 0x1D);
+    }
+
+    #[test]
+    fn short_branches() {
+        let (cpu, mem) = run(&[
+            0xF8, 0x00, // 300: LDI 00
+            0x32, 0x07, // 302: BZ 307
+            0xF8, 0x11, // 304: LDI 11
+            0xD4,       // 306: SEP R4
+            0xF8, 0x22, // 307: LDI 22
+            0xA7,       // 309: PLO R7
+            0x3A, 0x0E, // 30A: BNZ 30E
+            0xA8,       // 30C: PLO R8
+            0xD4,       // 30D: SEP R4
+            0x7B,       // 30E: SEQ
+            0x31, 0x12, // 30F: BQ 312
+            0xD4,       // 311: SEP R4
+            0x39, 0x00, // 312: BNQ 300
+            0xA9,       // 314: PLO R9
+            0x73,       // 315: STXD
+            0xD4,       // 316: SEP R4
+        ]);
+        assert_eq!(cpu.r[7], 0x22);
+        assert_eq!(cpu.r[8], 0);
+        assert_eq!(cpu.r[9], 0x22);
+        assert!(cpu.q);
+        assert_eq!(cpu.r[3], 0x317);
+        assert_eq!(cpu.r[2], STACK - 1);
+        assert_eq!(mem[STACK as usize], 0x22);
+    }
+
+    #[test]
+    fn long_branches_and_skips() {
+        let mut code = vec![
+            0xF8, 0x00,       // 300: LDI 00
+            0xC2, 0x03, 0x10, // 302: LBZ 310
+            0xD4,             // 305: SEP R4
+        ];
+        code.resize(0x10, 0);
+        code.extend_from_slice(&[
+            0xCA, 0x03, 0x05, // 310: LBNZ 305
+            0xCE,             // 313: LSZ
+            0xF8, 0xFF,       // 314: LDI FF
+            0xC4,             // 316: NOP
+            0xC8,             // 317: LSKP
+            0xF8, 0xEE,       // 318: LDI EE
+            0xF8, 0x33,       // 31A: LDI 33
+            0x73,             // 31C: STXD
+            0xD4,             // 31D: SEP R4
+        ]);
+        let (cpu, mem) = run(&code);
+        assert_eq!(cpu.d, 0x33);
+        assert_eq!(cpu.r[3], 0x31E);
+        assert_eq!(mem[STACK as usize], 0x33);
+    }
+
+    #[test]
+    fn long_branches_take_three_cycles() {
+        let mut mem = vec![0xC0, 0x01, 0x23, 0xF8, 0x00];
+        mem.resize(4096, 0);
+        let mut cpu = Cdp1802::new();
+        assert_eq!(cpu.step(&mut mem, &[]), LONG_CYCLES);
+        assert_eq!(cpu.r[0], 0x123);
+        cpu.r[0] = 3;
+        assert_eq!(cpu.step(&mut mem, &[]), SHORT_CYCLES);
+    }
+
+    #[test]
+    fn mark_and_ret() {
+        let mut code = vec![
+            0xF8, 0x03, 0xBC, // 300: LDI 03, PHI RC
+            0xF8, 0x10, 0xAC, // 303: LDI 10, PLO RC
+            0x79,             // 306: MARK
+            0xDC,             // 307: SEP RC
+            0xF8, 0x44,       // 308: LDI 44
+            0x73,             // 30A: STXD
+            0xD4,             // 30B: SEP R4
+        ];
+        code.resize(0x10, 0);
+        code.extend_from_slice(&[
+            0xF8, 0x55, 0xA7, // 310: LDI 55, PLO R7
+            0xE2,             // 313: SEX R2
+            0x60,             // 314: IRX
+            0x70,             // 315: RET
+        ]);
+        let (cpu, mem) = run(&code);
+        // MARK saved X = 2 and P = 3 in T and on the stack, and RET put them back
+        assert_eq!(cpu.t, 0x23);
+        assert_eq!(mem[STACK as usize], 0x23);
+        assert_eq!((cpu.x, cpu.r[3]), (2, 0x30C));
+        assert_eq!(cpu.r[7], 0x55);
+        assert_eq!(cpu.r[0xC], 0x316);
+        assert!(cpu.ie);
+        // RET left R2 just past what it popped, which is where STXD then wrote
+        assert_eq!(mem[STACK as usize + 1], 0x44);
+        assert_eq!(cpu.r[2], STACK);
+    }
+}
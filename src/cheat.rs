@@ -0,0 +1,194 @@
+// Memory searches for finding things like lives or score counters, and cheats
+// that hold addresses at a fixed value every frame.
+//
+// Cheat lists are text with one `address = value` line per cheat, both in hex.
+// Blank lines and anything after a # are ignored.
+
+use std::fmt;
+
+use crate::cpu::Emu;
+
+#[derive(Debug, PartialEq)]
+pub struct CheatError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+// How a value has to relate to the last snapshot to stay in the search
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compare {
+    Changed,
+    Unchanged,
+    Increased,
+    Decreased,
+    Equal(u8),
+}
+
+// Candidates are kept a bit per address, so searching MegaChip's 16MB costs 2MB on top of
+// the snapshot
+pub struct Search {
+    snapshot: Vec<u8>,
+    candidates: Vec<u64>,
+    count: usize,
+}
+
+impl Search {
+    // Start over with every address as a candidate
+    pub fn new(mem: &[u8]) -> Search {
+        let mut candidates = vec![u64::MAX; mem.len().div_ceil(64)];
+        // Past the end of memory in the last word
+        let tail = mem.len() % 64;
+        if tail != 0 {
+            *candidates.last_mut().unwrap() = (1 << tail) - 1;
+        }
+        Search {
+            snapshot: mem.to_vec(),
+            candidates,
+            count: mem.len(),
+        }
+    }
+
+    // Drop the candidates that don't match, then remember memory as it is now for next time
+    pub fn refine(&mut self, mem: &[u8], compare: Compare) {
+        self.count = 0;
+        for (index, word) in self.candidates.iter_mut().enumerate() {
+            let mut rest = *word;
+            while rest != 0 {
+                let bit = rest.trailing_zeros() as usize;
+                rest &= rest - 1;
+
+                let addr = index * 64 + bit;
+                let (old, new) = (self.snapshot[addr], mem[addr]);
+                let keep = match compare {
+                    Compare::Changed => new != old,
+                    Compare::Unchanged => new == old,
+                    Compare::Increased => new > old,
+                    Compare::Decreased => new < old,
+                    Compare::Equal(value) => new == value,
+                };
+                if !keep {
+                    *word &= !(1 << bit);
+                }
+            }
+            self.count += word.count_ones() as usize;
+        }
+        self.snapshot.copy_from_slice(mem);
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    // Candidate addresses in order, starting with the skip'th one
+    pub fn candidates(&self, skip: usize) -> impl Iterator<Item = usize> + '_ {
+        // Whole words are skipped by counting their bits
+        let mut skip = skip;
+        let mut first = self.candidates.len();
+        for (index, word) in self.candidates.iter().enumerate() {
+            let ones = word.count_ones() as usize;
+            if skip < ones {
+                first = index;
+                break;
+            }
+            skip -= ones;
+        }
+
+        self.candidates[first..].iter().enumerate().flat_map(move |(index, word)| {
+            let base = (first + index) * 64;
+            (0..64).filter(move |bit| word & (1 << bit) != 0).map(move |bit| base + bit)
+        })
+        .skip(skip)
+    }
+
+    // What an address held when the last snapshot was taken
+    pub fn previous(&self, addr: usize) -> u8 {
+        self.snapshot[addr]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cheat {
+    pub addr: u16,
+    pub value: u8,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CheatList {
+    cheats: Vec<Cheat>,
+}
+
+impl CheatList {
+    pub fn parse(text: &str) -> Result<CheatList, CheatError> {
+        let mut list = CheatList::default();
+
+        for (i, raw) in text.lines().enumerate() {
+            let line = raw.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let error = || CheatError { line: i + 1, message: String::from("expected `address = value` in hex") };
+            let (addr, value) = line.split_once('=').ok_or_else(error)?;
+            let hex = |text: &str| {
+                let text = text.trim();
+                let text = text.strip_prefix("0x").or(text.strip_prefix("0X")).unwrap_or(text);
+                u16::from_str_radix(text, 16).ok()
+            };
+            let addr = hex(addr).ok_or_else(error)?;
+            let value = hex(value).filter(|value| *value <= 0xFF).ok_or_else(error)?;
+
+            list.freeze(addr, value as u8);
+        }
+
+        Ok(list)
+    }
+
+    pub fn to_text(&self) -> String {
+        self.cheats.iter().map(|cheat| format!("{:#05X} = {:02X}\n", cheat.addr, cheat.value)).collect()
+    }
+
+    pub fn get(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cheats.is_empty()
+    }
+
+    // Hold addr at value, replacing any cheat already on that address
+    pub fn freeze(&mut self, addr: u16, value: u8) {
+        match self.cheats.iter_mut().find(|cheat| cheat.addr == addr) {
+            Some(cheat) => cheat.value = value,
+            None => self.cheats.push(Cheat { addr, value }),
+        }
+    }
+
+    pub fn unfreeze(&mut self, addr: u16) {
+        self.cheats.retain(|cheat| cheat.addr != addr);
+    }
+
+    pub fn is_frozen(&self, addr: u16) -> bool {
+        self.cheats.iter().any(|cheat| cheat.addr == addr)
+    }
+
+    pub fn clear(&mut self) {
+        self.cheats.clear();
+    }
+
+    // Write every cheat into memory, meant to be called once a frame.
+    // Addresses past the end of memory are skipped
+    pub fn apply(&self, emu: &mut Emu) {
+        let mem_size = emu.get_mem().len();
+        for cheat in &self.cheats {
+            if (cheat.addr as usize) < mem_size {
+                emu.set_mem(cheat.addr as usize, cheat.value);
+            }
+        }
+    }
+}
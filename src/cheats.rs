@@ -0,0 +1,65 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use yachip8emu::cheat::{CheatList, Search};
+use yachip8emu::rom::sha1_hex;
+
+// Cheats and the memory search for whichever ROM is running
+pub struct Cheats {
+    pub list: CheatList,
+    pub search: Option<Search>,
+    // Where the list is kept, named after the ROM's SHA-1, if there's a cheat directory
+    path: Option<PathBuf>,
+}
+
+impl Cheats {
+    pub fn load(dir: Option<&Path>, rom: &[u8]) -> Result<Cheats, String> {
+        let path = dir.map(|dir| dir.join(format!("{}.cht", sha1_hex(rom))));
+
+        let list = match &path {
+            Some(path) => match fs::read_to_string(path) {
+                Ok(text) => CheatList::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?,
+                // No cheats saved for this ROM yet
+                Err(e) if e.kind() == io::ErrorKind::NotFound => CheatList::default(),
+                Err(e) => return Err(format!("couldn't read cheats {}: {}", path.display(), e)),
+            },
+            None => CheatList::default(),
+        };
+
+        Ok(Cheats { list, search: None, path })
+    }
+
+    // Freeze addr at value, or let it go if it's already frozen, then save the list
+    pub fn toggle(&mut self, addr: u16, value: u8) -> Result<(), String> {
+        if self.list.is_frozen(addr) {
+            self.list.unfreeze(addr);
+        }
+        else {
+            self.list.freeze(addr, value);
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        if self.list.is_empty() {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                    Err(format!("couldn't remove {}: {}", path.display(), e))
+                },
+                _ => Ok(()),
+            }
+        }
+        else {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(|e| format!("couldn't create {}: {}", dir.display(), e))?;
+            }
+            fs::write(path, self.list.to_text()).map_err(|e| format!("couldn't write {}: {}", path.display(), e))
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+use yachip8emu::args::MachineArgs;
+use yachip8emu::args::PlatformArgs;
+
+use crate::filter::Filter;
+use crate::video::Palette;
+use crate::video::ScaleMode;
+
+#[derive(Parser)]
+#[command(name = "yachip8emu", version, about = "A simple CHIP-8 emulator")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Play a ROM in a window
+    Run(RunArgs),
+    /// Print an assembly listing of a ROM
+    Disasm {
+        rom: PathBuf,
+        #[command(flatten)]
+        platform: PlatformArgs,
+    },
+    /// Assemble a source file into a ROM
+    Asm {
+        source: PathBuf,
+        /// Where to write the ROM
+        #[arg(short, long)]
+        output: PathBuf,
+        #[command(flatten)]
+        platform: PlatformArgs,
+    },
+    /// Check a ROM: size, hash, likely platform, unreachable code and quirk-sensitive instructions
+    Info {
+        rom: PathBuf,
+        #[command(flatten)]
+        platform: PlatformArgs,
+    },
+    /// Run a ROM without a window and print the final screen
+    Test(TestArgs),
+}
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// ROM to start with; leave it out to start in the ROM browser
+    #[arg(required_unless_present = "rom_dir")]
+    pub rom: Option<PathBuf>,
+    /// Directory of ROMs to pick from in the browser (open it with Esc)
+    #[arg(long, value_name = "DIR")]
+    pub rom_dir: Option<PathBuf>,
+    /// ROM database giving titles, authors and quirks for the browser
+    #[arg(long, value_name = "FILE", requires = "rom_dir")]
+    pub rom_db: Option<PathBuf>,
+    #[command(flatten)]
+    pub machine: MachineArgs,
+    /// Initial window scale
+    #[arg(short, long, default_value_t = 15, value_parser = clap::value_parser!(u32).range(1..))]
+    pub scale: u32,
+    /// How the screen is fitted to the window: integer or aspect
+    #[arg(long, default_value = "integer", value_parser = parse_scale_mode)]
+    pub scale_mode: ScaleMode,
+    /// Draw pixel grid lines (toggle with F10)
+    #[arg(long)]
+    pub grid: bool,
+    /// Display filter: none, phosphor, blend, blend:N or deflicker (cycle with Tab)
+    #[arg(short, long, default_value = "none", value_parser = parse_filter)]
+    pub filter: Filter,
+    /// Background and foreground colours as hex, e.g. 5431a3,7b9ced
+    #[arg(long, value_parser = parse_palette)]
+    pub palette: Option<Palette>,
+    /// File of `key = hex digit` lines replacing the default key bindings
+    #[arg(long, value_name = "FILE")]
+    pub keymap: Option<PathBuf>,
+    /// Don't play the sound timer beep
+    #[arg(long)]
+    pub mute: bool,
+    /// Start with emulation paused (toggle with P)
+    #[arg(long)]
+    pub paused: bool,
+    /// Show frames and instructions per second (toggle with F9)
+    #[arg(long)]
+    pub stats: bool,
+    /// Open the debugger window at startup (toggle with F12)
+    #[arg(long)]
+    pub debug: bool,
+    /// Directory to keep each ROM's cheats in, named after its SHA-1
+    #[arg(long, value_name = "DIR")]
+    pub cheat_dir: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct TestArgs {
+    pub rom: PathBuf,
+    #[command(flatten)]
+    pub machine: MachineArgs,
+    /// Number of 60Hz frames to run for
+    #[arg(long, default_value_t = 600)]
+    pub frames: u32,
+    /// Compile the ROM to native code as it runs instead of interpreting it
+    #[cfg(feature = "jit")]
+    #[arg(long)]
+    pub jit: bool,
+}
+
+fn parse_scale_mode(name: &str) -> Result<ScaleMode, String> {
+    ScaleMode::from_name(name).ok_or(format!("unknown scale mode '{}'", name))
+}
+
+fn parse_filter(name: &str) -> Result<Filter, String> {
+    Filter::from_name(name).ok_or(format!("unknown filter '{}'", name))
+}
+
+fn parse_palette(text: &str) -> Result<Palette, String> {
+    Palette::from_hex(text).ok_or(format!("'{}' isn't two hex colours like 5431a3,7b9ced", text))
+}
@@ -1,89 +1,606 @@
+use rand::rngs::SmallRng;
 use rand::Rng;
+use rand::SeedableRng;
+
+use std::io::Write;
+#[cfg(feature = "jit")]
+use std::panic;
+
+use crate::cdp1802::Cdp1802;
+use crate::disasm;
+use crate::dispatch::{DispatchCache, Inst};
+use crate::font::{self, Font, FontError, FontSet};
+use crate::framebuffer::Framebuffer;
+#[cfg(feature = "jit")]
+use crate::jit::{self, Jit};
+use crate::megachip::{self, MegaChip, SoundEvent};
 
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
+// The hi-res VIP interpreter shows two pages of display memory at once
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
 
 const MEM_SIZE: usize = 4096;
+// Room for the deepest stack any supported machine has
 const STACK_SIZE: usize = 16;
+pub const VIP_STACK_DEPTH: usize = 12;
+pub const SCHIP_STACK_DEPTH: usize = 16;
 const NUM_V: usize = 16;
-const START_ADDR: u16 = 0x200;
+pub const START_ADDR: u16 = 0x200;
 const NUM_KEYS: usize = 16;
+// Bumped whenever the save state layout changes
+const STATE_VERSION: u8 = 6;
+// Used where there's no clock to seed from, e.g. in the browser
+const DEFAULT_SEED: u64 = 0x5EED_C8C8;
+
+
+// Where the VIP's interpreter keeps what 0NNN machine code works on
+const VIP_V_ADDR: usize = 0xEF0;
+const VIP_DISPLAY_ADDR: usize = 0xF00;
+// Top of the interpreter's work stack, which R2 points into
+const VIP_STACK_ADDR: u16 = 0xECF;
+// Machine cycles a 0NNN call can run before we decide it's stuck, about a second on a VIP
+const MACHINE_CODE_LIMIT: u32 = 220_000;
+
+// The VIP's 1802 runs at 1.76064MHz, 8 clocks to a machine cycle, and the 1861 video chip
+// interrupts it once a frame to show the display and count the timers down
+pub const VIP_CYCLES_PER_SECOND: u32 = 1_760_640 / 8;
+const VIP_FRAME_CYCLES: i32 = 3668;
+// Cycles of each frame spent on the interrupt routine and display DMA rather than CHIP-8
+const VIP_INTERRUPT_CYCLES: i32 = 1024 + 29;
+
+// CHIP-8X's interpreter is bigger, so its programs start further up
+const CHIP8X_START_ADDR: u16 = 0x300;
+// Hi-res programs start with a jump over the interpreter patch to the program proper
+const HIRES_BOOTSTRAP: [u8; 2] = [0x12, 0x60];
+const HIRES_PROGRAM_ADDR: u16 = 0x260;
+// MegaChip programs switch themselves into MegaChip mode straight away
+const MEGACHIP_BOOTSTRAP: [u8; 2] = [0x00, 0x11];
+// The ETI-660's interpreter takes up memory below 0x600, and its screen is 48 rows tall
+const ETI660_START_ADDR: u16 = 0x600;
+const ETI660_SCREEN_HEIGHT: usize = 48;
+// The VP-590 colours the display in zones 8 pixels wide and one row high
+pub const ZONE_WIDTH: usize = 8;
+const ZONE_COLUMNS: usize = SCREEN_WIDTH / ZONE_WIDTH;
+// BXY0 works in blocks of four zone rows
+const BLOCK_HEIGHT: usize = 4;
+// VP-590 colours are 0-7: black, red, blue, violet, green, yellow, aqua and white
+const DEFAULT_ZONE_COLOR: u8 = 1;
+// 02A0 steps the background through blue, black, green and red
+const BACKGROUND_COLORS: [u8; 4] = [2, 0, 4, 1];
+
+// Where the font goes unless set_font_addr moves it
+pub const FONT_ADDR: u16 = 0;
+
+// Region of the display touched since a frontend last looked, in pixels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirtyRect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl DirtyRect {
+    fn union(&self, other: &DirtyRect) -> DirtyRect {
+        let left = self.x.min(other.x);
+        let top = self.y.min(other.y);
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
 
-const FONTSET_SIZE: usize = 80;
-const FONTSET: [u8; FONTSET_SIZE] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80 // F
-];
+        DirtyRect { x: left, y: top, width: right - left, height: bottom - top }
+    }
+}
+
+// Reported to the draw hook every time an instruction changes the display
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DrawEvent {
+    Clear,
+    Sprite { x: usize, y: usize, rows: usize, collision: bool },
+    // MegaChip mode put the frame it had been drawing on screen
+    Frame,
+}
+
+// Which machine's interpreter to behave like
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Platform {
+    #[default]
+    Chip8,
+    // COSMAC VIP with the VP-590 colour board and a second keypad
+    Chip8X,
+    // The 64x64 hi-res VIP interpreter
+    Chip8Hires,
+    // MegaChip-8, which runs as CHIP-8 until 0011 turns on MegaChip mode
+    MegaChip,
+    // The ETI-660, with programs at 0x600 and a 64x48 screen
+    Eti660,
+    // The DREAM 6800, with its own font and a keypad that can only see one key at a time
+    Dream6800,
+    // CHIP-8E, the VIP interpreter with extra skips, relative jumps and port I/O
+    Chip8E,
+}
+
+impl Platform {
+    // Recognise hi-res and MegaChip programs by their first instruction. Anything else is
+    // taken for CHIP-8
+    pub fn detect(rom: &[u8]) -> Platform {
+        if rom.starts_with(&HIRES_BOOTSTRAP) {
+            Platform::Chip8Hires
+        }
+        else if rom.starts_with(&MEGACHIP_BOOTSTRAP) {
+            Platform::MegaChip
+        }
+        else {
+            Platform::Chip8
+        }
+    }
+
+    // Where programs are loaded and start running
+    pub fn start_addr(&self) -> u16 {
+        match self {
+            Platform::Chip8X => CHIP8X_START_ADDR,
+            Platform::Eti660 => ETI660_START_ADDR,
+            Platform::Chip8 | Platform::Chip8Hires | Platform::MegaChip | Platform::Dream6800 | Platform::Chip8E => {
+                START_ADDR
+            },
+        }
+    }
+
+    // Where the first byte of rom ends up, which is after the bootstrap jump for hi-res
+    // programs that don't have their own
+    pub fn load_addr(&self, rom: &[u8]) -> u16 {
+        if *self == Platform::Chip8Hires && !rom.starts_with(&HIRES_BOOTSTRAP) {
+            HIRES_PROGRAM_ADDR
+        }
+        else {
+            self.start_addr()
+        }
+    }
+
+    pub fn mem_size(&self) -> usize {
+        match self {
+            Platform::MegaChip => megachip::MEM_SIZE,
+            Platform::Chip8 | Platform::Chip8X | Platform::Chip8Hires | Platform::Eti660 | Platform::Dream6800
+                | Platform::Chip8E => MEM_SIZE,
+        }
+    }
+
+    // Nested calls the machine's own interpreter has room for. The VIP's interpreters keep 12
+    pub fn stack_depth(&self) -> usize {
+        match self {
+            Platform::Chip8X | Platform::Chip8Hires | Platform::Chip8E => VIP_STACK_DEPTH,
+            Platform::Chip8 | Platform::MegaChip | Platform::Eti660 | Platform::Dream6800 => SCHIP_STACK_DEPTH,
+        }
+    }
+
+    pub fn max_rom_size(&self) -> usize {
+        self.mem_size() - self.start_addr() as usize
+    }
+
+    // Height of the screen outside MegaChip mode
+    pub fn screen_height(&self) -> usize {
+        match self {
+            Platform::Chip8Hires => HIRES_SCREEN_HEIGHT,
+            Platform::Eti660 => ETI660_SCREEN_HEIGHT,
+            Platform::Chip8 | Platform::Chip8X | Platform::MegaChip | Platform::Dream6800 | Platform::Chip8E => {
+                SCREEN_HEIGHT
+            },
+        }
+    }
+
+    // The font the machine came with, used unless set_font picks another
+    pub fn font_set(&self) -> FontSet {
+        match self {
+            Platform::Eti660 => FontSet::Eti660,
+            Platform::Dream6800 => FontSet::Dream6800,
+            Platform::Chip8 | Platform::Chip8X | Platform::Chip8Hires | Platform::MegaChip | Platform::Chip8E => {
+                FontSet::Modern
+            },
+        }
+    }
+
+    // The biggest the screen can get, for frontends that need to know up front
+    pub fn max_screen_size(&self) -> (usize, usize) {
+        match self {
+            Platform::MegaChip => (megachip::WIDTH, megachip::HEIGHT),
+            Platform::Chip8 | Platform::Chip8X | Platform::Chip8Hires | Platform::Eti660 | Platform::Dream6800
+                | Platform::Chip8E => (SCREEN_WIDTH, self.screen_height()),
+        }
+    }
+
+    // Whether a font at addr ends before programs start
+    pub fn font_fits(&self, addr: u16) -> bool {
+        addr as usize + font::FONT_SIZE <= self.start_addr() as usize
+    }
+
+    fn display_len(&self) -> usize {
+        let (width, height) = self.max_screen_size();
+        width * height
+    }
+}
+
+// Something a real machine would have crashed on. The emulator stops until it's reset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    StackOverflow { pc: u16 },
+    StackUnderflow { pc: u16 },
+    MachineCodeHung { pc: u16 },
+    JumpOutOfRange { pc: u16 },
+    MachineCodeUnsupported { pc: u16 },
+    UnknownOpcode { pc: u16, op: u16 },
+}
+
+impl std::fmt::Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Fault::StackOverflow { pc } => write!(f, "stack overflow at {:#05X}", pc),
+            Fault::StackUnderflow { pc } => write!(f, "return with an empty stack at {:#05X}", pc),
+            Fault::MachineCodeHung { pc } => write!(f, "machine code called at {:#05X} never returned", pc),
+            Fault::JumpOutOfRange { pc } => write!(f, "jump out of memory at {:#05X}", pc),
+            Fault::UnknownOpcode { pc, op } => write!(f, "unknown instruction {:04X} at {:#05X}", op, pc),
+            Fault::MachineCodeUnsupported { pc } => {
+                write!(f, "machine code called at {:#05X} needs a 64x32 screen", pc)
+            },
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StateError {
+    WrongSize,
+    WrongVersion(u8),
+    // Values no running machine could have, e.g. more return addresses than the stack holds
+    Corrupt,
+}
+
+impl std::fmt::Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            StateError::WrongSize => write!(f, "save state has the wrong size"),
+            StateError::WrongVersion(v) => write!(f, "save state version {} is not supported", v),
+            StateError::Corrupt => write!(f, "save state is corrupt"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LoadError {
+    TooLarge { size: usize, max: usize },
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadError::TooLarge { size, max } => {
+                write!(f, "ROM is {} bytes but at most {} bytes fit in memory", size, max)
+            }
+        }
+    }
+}
 
 pub struct Emu {
-    mem: [u8; MEM_SIZE],
-    display: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
+    // Sized for the platform
+    mem: Vec<u8>,
+    // Sized for the screen as it is right now, which MegaChip mode changes
+    display: Framebuffer,
     pc: u16,
-    i_reg: u16,
-    stack: Vec<u16>,
+    // Only MegaChip mode uses more than 16 bits
+    i_reg: u32,
+    stack: [u16; STACK_SIZE],
+    // Number of return addresses on the stack
+    sp: usize,
+    // Calls allowed before the stack overflows, and the depth asked for in place of the platform's
+    stack_depth: usize,
+    stack_depth_override: Option<usize>,
+    fault: Option<Fault>,
     d_timer: u8,
     s_timer: u8,
     v_reg: [u8; NUM_V],
     keys: [bool; NUM_KEYS],
     cosmac: bool,
+    platform: Platform,
+    // CHIP-8X colours: a position in BACKGROUND_COLORS and the foreground of each zone
+    background: usize,
+    zone_colors: [u8; ZONE_COLUMNS * SCREEN_HEIGHT],
+    // CHIP-8X's second keypad, tested by EXF2 and EXF5
+    keys2: [bool; NUM_KEYS],
+    // Last byte FXF8 sent to the VP-595 tone generator (or FX03 to CHIP-8E's port 3), and
+    // what FXFB and FXE7 read back
+    port_out: Option<u8>,
+    port_in: u8,
+    // Set while a MegaChip program is in MegaChip mode
+    mega: Option<Box<MegaChip>>,
+    // A font replacing the platform's, and where in memory fonts go
+    font: Option<Font>,
+    font_addr: u16,
+    // Decoded instructions by address, made the first time run_ticks is called
+    dispatch: Option<DispatchCache>,
+    // Compiled blocks, made the first time run_jit is called
+    #[cfg(feature = "jit")]
+    jit: Option<Box<Jit>>,
+    // Set while CHIP-8E's FX4F waits for the delay timer it started
+    timer_wait: bool,
+    // Charge instructions their COSMAC VIP machine cycles, run a frame at a time with run_frame
+    vip_timing: bool,
+    // Machine cycles left in this frame, negative when the last instruction ran over
+    cycle_budget: i32,
+    // Grows whenever the display changes, cleared when a frontend reads it
+    dirty_rect: Option<DirtyRect>,
+    draw_hook: Option<Box<dyn FnMut(DrawEvent) + Send>>,
+    // Executed instructions are logged here when set
+    trace: Option<Box<dyn Write + Send>>,
+    rng: SmallRng,
+}
+
+impl Default for Emu {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Emu {
     pub fn new() -> Self {
         let mut emu_inst = Self {
-            mem: [0; MEM_SIZE],
-            display: [false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            mem: vec![0; MEM_SIZE],
+            display: Framebuffer::new(SCREEN_WIDTH, Platform::Chip8.screen_height()),
             pc: START_ADDR,
             i_reg: 0,
-            stack: Vec::new(),
+            stack: [0; STACK_SIZE],
+            sp: 0,
+            stack_depth: SCHIP_STACK_DEPTH,
+            stack_depth_override: None,
+            fault: None,
             d_timer: 0,
             s_timer: 0,
             v_reg: [0; NUM_V],
             keys: [false; NUM_KEYS],
-            cosmac: false
+            cosmac: false,
+            platform: Platform::Chip8,
+            background: 0,
+            zone_colors: [DEFAULT_ZONE_COLOR; ZONE_COLUMNS * SCREEN_HEIGHT],
+            keys2: [false; NUM_KEYS],
+            port_out: None,
+            port_in: 0,
+            mega: None,
+            font: None,
+            font_addr: FONT_ADDR,
+            dispatch: None,
+            #[cfg(feature = "jit")]
+            jit: None,
+            timer_wait: false,
+            vip_timing: false,
+            cycle_budget: 0,
+            dirty_rect: None,
+            draw_hook: None,
+            trace: None,
+            rng: SmallRng::seed_from_u64(initial_seed()),
         };
 
-        emu_inst.mem[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        emu_inst.load_font();
+        emu_inst.dirty_rect = Some(emu_inst.full_screen());
 
         emu_inst
     }
 
+    // Quirk settings are left alone so a restarted game behaves the same way
     pub fn reset(&mut self) {
-        self.mem = [0; MEM_SIZE];
-        self.display = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
-        self.pc = START_ADDR;
+        self.mem = vec![0; self.platform.mem_size()];
+        self.pc = self.platform.start_addr();
         self.i_reg = 0;
-        self.stack = Vec::new();
+        self.stack = [0; STACK_SIZE];
+        self.sp = 0;
+        self.fault = None;
         self.d_timer = 0;
         self.s_timer = 0;
         self.v_reg = [0; NUM_V];
         self.keys = [false; NUM_KEYS];
-        self.cosmac = false;
-        self.mem[..FONTSET_SIZE].copy_from_slice(&FONTSET);
+        self.background = 0;
+        self.zone_colors = [DEFAULT_ZONE_COLOR; ZONE_COLUMNS * SCREEN_HEIGHT];
+        self.keys2 = [false; NUM_KEYS];
+        self.port_out = None;
+        self.port_in = 0;
+        self.mega = None;
+        self.display = self.blank_display();
+        self.cycle_budget = 0;
+        self.timer_wait = false;
+        self.dirty_rect = Some(self.full_screen());
+        self.load_font();
+        self.forget_decoded();
     }
 
     pub fn set_cosmac(&mut self) {
         self.cosmac = true;
     }
 
+    // Call before load, since the platform decides where the program goes
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        self.pc = platform.start_addr();
+        self.mem.resize(platform.mem_size(), 0);
+        self.mega = None;
+        self.display = self.blank_display();
+        self.stack_depth = self.stack_depth_override.unwrap_or(platform.stack_depth());
+        // Programs may start lower on this platform
+        if !platform.font_fits(self.font_addr) {
+            self.font_addr = FONT_ADDR;
+        }
+        self.load_font();
+        self.forget_decoded();
+        // The screen may have changed size
+        self.mark_dirty(self.full_screen());
+    }
+
+    // Use this font instead of the platform's own, or None to go back to it
+    pub fn set_font(&mut self, font: Option<Font>) {
+        self.font = font;
+        self.load_font();
+    }
+
+    // Move the font, e.g. to 0x50 where some interpreters keep it. It has to fit below the
+    // program, so call this after set_platform and before load
+    pub fn set_font_addr(&mut self, addr: u16) -> Result<(), FontError> {
+        if !self.platform.font_fits(addr) {
+            return Err(FontError::OverlapsProgram { addr, start: self.platform.start_addr() });
+        }
+        self.font_addr = addr;
+        self.load_font();
+        Ok(())
+    }
+
+    fn load_font(&mut self) {
+        let font = match &self.font {
+            Some(font) => font.clone(),
+            None => Font::builtin(self.platform.font_set()),
+        };
+        font.write_to(&mut self.mem, self.font_addr as usize);
+        self.invalidate(self.font_addr as usize, font::FONT_SIZE);
+    }
+
+    pub fn set_vip_timing(&mut self) {
+        self.vip_timing = true;
+    }
+
+    // Call after writing len bytes of memory from addr
+    fn invalidate(&mut self, addr: usize, len: usize) {
+        if let Some(cache) = self.dispatch.as_mut() {
+            cache.invalidate(addr, len);
+        }
+        #[cfg(feature = "jit")]
+        if let Some(jit) = self.jit.as_mut() {
+            jit.invalidate(addr, len);
+        }
+    }
+
+    // Call when memory may have changed anywhere
+    fn forget_decoded(&mut self) {
+        self.dispatch = None;
+        #[cfg(feature = "jit")]
+        {
+            self.jit = None;
+        }
+    }
+
+    // How many nested calls fit before 2NNN faults, up to 16, on every platform from now on
+    pub fn set_stack_depth(&mut self, depth: usize) {
+        self.stack_depth = depth.clamp(1, STACK_SIZE);
+        self.stack_depth_override = Some(self.stack_depth);
+    }
+
+    // Make CXNN produce a repeatable sequence
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+
+    pub fn set_trace(&mut self, trace: Option<Box<dyn Write + Send>>) {
+        self.trace = trace;
+    }
+
     pub fn tick(&mut self) {
+        if self.fault.is_some() {
+            return;
+        }
+
+        let pc = self.pc;
         let op = self.fetch();
-        print!("{}:   ", self.pc);
+        if let Some(out) = self.trace.as_mut() {
+            // A broken trace file shouldn't stop the game
+            let _ = writeln!(out, "{:#05X}: {:04X}  {}", pc, op, disasm::disassemble(op, self.platform));
+        }
+        let vx = self.v_reg[((op & 0x0F00) >> 8) as usize];
         self.execute(op);
+        if self.vip_timing {
+            self.cycle_budget -= vip_cycles(op, vx, pc, self.pc) as i32;
+        }
+    }
+
+    // Run up to count instructions, stopping early on a fault. Each instruction is decoded
+    // once into a cache and again only when something writes over it, which makes this much
+    // faster than calling tick count times. Traces and VIP timing still go through tick
+    pub fn run_ticks(&mut self, count: u32) {
+        if self.trace.is_some() || self.vip_timing {
+            for _ in 0..count {
+                self.tick();
+            }
+            return;
+        }
+
+        let mem_len = self.mem.len();
+        for _ in 0..count {
+            if self.fault.is_some() {
+                break;
+            }
+
+            let cache = self.dispatch.get_or_insert_with(|| DispatchCache::new(mem_len));
+            let decoded = cache.get(&self.mem, self.pc);
+            self.pc += 2;
+            match decoded.inst {
+                Inst::Other => self.execute(decoded.op),
+                inst => self.execute_decoded(inst),
+            }
+        }
+    }
+
+    // Run up to count instructions like run_ticks, compiling blocks of them to native code
+    // the first time they're reached. Whatever a block can't do is interpreted, as is
+    // everything while tracing or with VIP timing on
+    #[cfg(feature = "jit")]
+    pub fn run_jit(&mut self, count: u32) {
+        if self.trace.is_some() || self.vip_timing {
+            self.run_ticks(count);
+            return;
+        }
+
+        let layout = jit::Layout {
+            v_reg: std::mem::offset_of!(Emu, v_reg) as i32,
+            i_reg: std::mem::offset_of!(Emu, i_reg) as i32,
+            pc: std::mem::offset_of!(Emu, pc) as i32,
+        };
+        let mem_len = self.mem.len();
+        let mut left = count;
+        while left > 0 && self.fault.is_none() {
+            let jit = self.jit.get_or_insert_with(|| Box::new(Jit::new(mem_len, layout, jit_execute)));
+            match jit.get(&self.mem, self.pc) {
+                // A block runs to the end, so only when there's room for all of it
+                Some(block) if block.len <= left => {
+                    let emu: *mut Emu = self;
+                    // Safety: the block came from this Emu's Jit and nothing else is borrowed
+                    match unsafe { block.run(emu) } {
+                        Some(pc) => self.pc = pc,
+                        None => {
+                            let payload = self.jit.as_mut().and_then(|jit| jit.take_panic());
+                            panic::resume_unwind(payload.unwrap_or_else(|| Box::new("compiled block panicked")));
+                        },
+                    }
+                    left -= block.len;
+                },
+                _ => {
+                    self.run_ticks(1);
+                    left -= 1;
+                },
+            }
+        }
+    }
+
+    // Run one display frame of a COSMAC VIP, with set_vip_timing on: as many instructions as
+    // fit in the cycles between interrupts, then the timer countdown the interrupt does.
+    // Returns how many instructions ran
+    pub fn run_frame(&mut self) -> u32 {
+        self.cycle_budget += VIP_FRAME_CYCLES - VIP_INTERRUPT_CYCLES;
+
+        let mut ticks = 0;
+        while self.cycle_budget > 0 && self.fault.is_none() {
+            // The VIP's sprite routine waits for the next interrupt before it draws, so DXYN
+            // can only be the first instruction of a frame
+            if ticks > 0 && self.mem[self.pc as usize] >> 4 == 0xD {
+                self.cycle_budget = 0;
+                break;
+            }
+            self.tick();
+            ticks += 1;
+        }
+
+        self.tick_timers();
+        ticks
     }
 
     pub fn tick_timers(& mut self) {
@@ -117,43 +634,138 @@ impl Emu {
 
         match(nibble1, nibble2, nibble3, nibble4) {
             // 00E0: Clear screen
-            (0, 0, 0xE, 0) => {
-                self.display = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
-                println!("CLS");
-            },
+            (0, 0, 0xE, 0) => self.clear_screen(),
+
+            // 0230: Clear screen, the hi-res interpreter's own version of 00E0
+            (0, 2, 3, 0) if self.platform == Platform::Chip8Hires => self.clear_screen(),
 
             // 00EE: Return from subroutine
             (0, 0, 0xE, 0xE) => {
-                let addr = self.stack.pop();
-
-                match addr {
-                    Some(x) => {
-                        self.pc = x;
-                        println!("RET")
-                    },
-                    None => {
-                        println!("The stack was unexpectedly empty.")
-                    }
+                if self.sp == 0 {
+                    self.fault = Some(Fault::StackUnderflow { pc: self.pc - 2 });
+                    return;
+                }
+                self.sp -= 1;
+                self.pc = self.stack[self.sp];
+            },
+
+            // 00ED: Stop (CHIP-8E)
+            (0, 0, 0xE, 0xD) if self.platform == Platform::Chip8E => {
+                self.pc -= 2;
+            },
+
+            // 00F2: Do nothing (CHIP-8E)
+            (0, 0, 0xF, 2) if self.platform == Platform::Chip8E => (),
+
+            // 0151: Wait for the delay timer to run out (CHIP-8E)
+            (0, 1, 5, 1) if self.platform == Platform::Chip8E => {
+                if self.d_timer > 0 {
+                    self.pc -= 2;
+                }
+            },
+
+            // 0188: Skip the next instruction (CHIP-8E)
+            (0, 1, 8, 8) if self.platform == Platform::Chip8E => {
+                self.pc += 2;
+            },
+
+            // 02A0: Step to the next background colour (CHIP-8X)
+            (0, 2, 0xA, 0) if self.platform == Platform::Chip8X => {
+                self.background = (self.background + 1) % BACKGROUND_COLORS.len();
+                self.mark_dirty(self.full_screen());
+            },
+
+            // 0010: Leave MegaChip mode
+            (0, 0, 1, 0) if self.platform == Platform::MegaChip => {
+                if self.mega.take().is_some() {
+                    self.display = self.blank_display();
+                    self.mark_dirty(self.full_screen());
+                }
+            },
+
+            // 0011: Enter MegaChip mode, with a 256x192 colour screen
+            (0, 0, 1, 1) if self.platform == Platform::MegaChip => {
+                if self.mega.is_none() {
+                    self.mega = Some(Box::default());
+                    self.display = self.blank_display();
+                    self.mark_dirty(self.full_screen());
                 }
             },
 
+            // 00BN: Scroll the picture being drawn up N rows (MegaChip)
+            (0, 0, 0xB, _) if self.mega.is_some() => {
+                self.mega.as_mut().unwrap().scroll_up(nibble4 as usize);
+            },
+
+            // 01NN NNNN: Set index to a 24-bit address, the low 16 bits in the next word (MegaChip)
+            (0, 1, _, _) if self.mega.is_some() => {
+                let nn = (op & 0x00FF) as u32;
+                let low = self.fetch() as u32;
+
+                self.i_reg = (nn << 16) | low;
+            },
+
+            // 02NN: Load NN palette colours from I (MegaChip)
+            (0, 2, _, _) if self.mega.is_some() => {
+                let nn = (op & 0x00FF) as usize;
+
+                self.mega.as_mut().unwrap().load_palette(&self.mem, self.i_reg as usize, nn);
+            },
+
+            // 03NN: Set sprite width (MegaChip)
+            (0, 3, _, _) if self.mega.is_some() => {
+                self.mega.as_mut().unwrap().set_sprite_width(op as u8);
+            },
+
+            // 04NN: Set sprite height (MegaChip)
+            (0, 4, _, _) if self.mega.is_some() => {
+                self.mega.as_mut().unwrap().set_sprite_height(op as u8);
+            },
+
+            // 05NN: Set screen alpha (MegaChip)
+            (0, 5, _, _) if self.mega.is_some() => {
+                self.mega.as_mut().unwrap().set_screen_alpha(op as u8);
+            },
+
+            // 060N: Play the sampled sound at I, looping if N is 0 (MegaChip)
+            (0, 6, 0, _) if self.mega.is_some() => {
+                self.mega.as_mut().unwrap().play(&self.mem, self.i_reg as usize, nibble4 == 0);
+            },
+
+            // 0700: Stop sampled sound (MegaChip)
+            (0, 7, 0, 0) if self.mega.is_some() => {
+                self.mega.as_mut().unwrap().stop();
+            },
+
+            // 080N: Set sprite blend mode (MegaChip)
+            (0, 8, 0, _) if self.mega.is_some() => {
+                self.mega.as_mut().unwrap().set_blend(nibble4 as u8);
+            },
+
+            // 09NN: Set the palette index that sprites collide with (MegaChip)
+            (0, 9, _, _) if self.mega.is_some() => {
+                self.mega.as_mut().unwrap().set_collision_color(op as u8);
+            },
+
             // 1NNN: Jump 
             (1, _, _, _) => {
                 let nnn = op & 0x0FFF;
 
                 self.pc = nnn;
-                
-                println!("JP {}", nnn);
             },
 
             // 2NNN: Call subroutine
             (2, _, _, _) => {
                 let nnn = op & 0x0FFF;
 
-                self.stack.push(nnn);
+                if self.sp >= self.stack_depth {
+                    self.fault = Some(Fault::StackOverflow { pc: self.pc - 2 });
+                    return;
+                }
+                // PC has already moved past the call, so that's where to come back to
+                self.stack[self.sp] = self.pc;
+                self.sp += 1;
                 self.pc = nnn;
-
-                println!("CALL {}", nnn);
             },
 
             // 3XNN: Skip if VX = NN
@@ -164,8 +776,6 @@ impl Emu {
                 if self.v_reg[x] == nn {
                     self.pc += 2;
                 }
-
-                println!("SE V{}, {}", x, nn);
             },
 
             // 4XNN: Skip if VX != NN
@@ -176,8 +786,6 @@ impl Emu {
                 if self.v_reg[x] != nn {
                     self.pc +=2;
                 }
-
-                println!("SNE V{}. {}", x, nn);
             }
 
             // 5XY0: Skip if VX = VY
@@ -188,18 +796,55 @@ impl Emu {
                 if self.v_reg[x] == self.v_reg[y] {
                     self.pc += 2;
                 }
-
-                println!("SE V{}, V{}", x, y);
             }
 
+            // 5XY1: Add VY to VX a nibble at a time, each wrapping at 8 (CHIP-8X)
+            (5, _, _, 1) if self.platform == Platform::Chip8X => {
+                let x = nibble2 as usize;
+                let y = nibble3 as usize;
+
+                let high = ((self.v_reg[x] >> 4) + (self.v_reg[y] >> 4)) & 0x7;
+                let low = ((self.v_reg[x] & 0xF) + (self.v_reg[y] & 0xF)) & 0x7;
+                self.v_reg[x] = (high << 4) | low;
+            },
+
+            // 5XY1: Skip if VX is greater than VY (CHIP-8E)
+            (5, _, _, 1) if self.platform == Platform::Chip8E => {
+                let x = nibble2 as usize;
+                let y = nibble3 as usize;
+
+                if self.v_reg[x] > self.v_reg[y] {
+                    self.pc += 2;
+                }
+            },
+
+            // 5XY2: Store VX to VY in memory starting at I, leaving I alone (CHIP-8E)
+            (5, _, _, 2) if self.platform == Platform::Chip8E => {
+                let x = nibble2 as usize;
+                let y = nibble3 as usize;
+
+                for (i, reg) in (x..=y).enumerate() {
+                    self.mem[self.i_reg as usize + i] = self.v_reg[reg];
+                }
+                self.invalidate(self.i_reg as usize, y.saturating_sub(x) + 1);
+            },
+
+            // 5XY3: Load VX to VY from memory starting at I, leaving I alone (CHIP-8E)
+            (5, _, _, 3) if self.platform == Platform::Chip8E => {
+                let x = nibble2 as usize;
+                let y = nibble3 as usize;
+
+                for (i, reg) in (x..=y).enumerate() {
+                    self.v_reg[reg] = self.mem[self.i_reg as usize + i];
+                }
+            },
+
             // 6XNN: Set
             (6, _, _, _) => {
                 let x = nibble2 as usize;
                 let nn = (op & 0x00FF) as u8;
 
                 self.v_reg[x] = nn;
-
-                println!("LD V{}, {}", x, nn);
             },
             
             // 7XNN: Add
@@ -208,8 +853,6 @@ impl Emu {
                 let nn = (op & 0x00FF) as u8;
 
                 self.v_reg[x] = self.v_reg[x].wrapping_add(nn);
-
-                println!("ADD V{}, {}", x, nn);
             },
 
             // 8XY0: Set
@@ -218,8 +861,6 @@ impl Emu {
                 let y = nibble3 as usize;
 
                 self.v_reg[x] = self.v_reg[y];
-
-                println!("LD V{}, V{}", x, y);
             } ,
 
             // 8XY1: Binary OR
@@ -228,8 +869,6 @@ impl Emu {
                 let y = nibble3 as usize;
 
                 self.v_reg[x] |= self.v_reg[y];
-
-                println!("OR V{}, V{}", x, y);
             },
 
             // 8XY2: Binary AND
@@ -238,8 +877,6 @@ impl Emu {
                 let y = nibble3 as usize;
 
                 self.v_reg[x] &= self.v_reg[y];
-
-                println!("AND V{}, V{}", x, y);
             },
 
             // 8XY3: Binary XOR
@@ -248,8 +885,6 @@ impl Emu {
                 let y = nibble3 as usize;
 
                 self.v_reg[x] ^= self.v_reg[y];
-
-                println!("XOR V{}, V{}", x, y);
             },
 
             // 8XY4: Add with carry
@@ -267,8 +902,6 @@ impl Emu {
                 }
 
                 self.v_reg[x] = sum;
-
-                println!("ADD V{}, V{}", x, y);
             },
 
             // 8XY5: Subtract VY from VX
@@ -286,8 +919,6 @@ impl Emu {
                 }
 
                 self.v_reg[x] = diff;
-
-                println!("SUB V{}, V{}", x, y);
             },
 
             // 8XY6: Shift to right
@@ -302,8 +933,6 @@ impl Emu {
                 let lsb = self.v_reg[x] & 1;
                 self.v_reg[x] >>= 1;
                 self.v_reg[0xF] = lsb;
-
-                println!("SHR V{}, V{}", x, y);
             },
 
             // 8XY7: Subtract VX from VY
@@ -321,7 +950,6 @@ impl Emu {
                 }
 
                 self.v_reg[x] = diff;
-                println!("SUBN V{}, V{}", x, y);
             },
 
             // 8XYE: Shift to left
@@ -346,20 +974,76 @@ impl Emu {
                 if self.v_reg[x] != self.v_reg[y] {
                     self.pc += 2;
                 }
-                println!("SNE V{}, V{}", x, y);
             },
 
             // ANNN: Set index
             (0xA, _, _, _) => {
                 let nnn = op & 0x0FFF;
-                self.i_reg = nnn;
-                println!("LD I, {}", nnn);
+                self.i_reg = nnn as u32;
+            },
+
+            // BXY0: Colour blocks of zones with VY (CHIP-8X). The low nibble of VX is the first
+            // column and its high nibble how many more follow, and VX+1 does the same for rows
+            // of blocks
+            (0xB, _, _, 0) if self.platform == Platform::Chip8X => {
+                let x = nibble2 as usize;
+                let y = nibble3 as usize;
+
+                let horizontal = self.v_reg[x] as usize;
+                let vertical = self.v_reg[(x + 1) % NUM_V] as usize;
+                let color = self.v_reg[y] & 0x7;
+
+                let columns = (horizontal & 0xF)..=(horizontal & 0xF) + (horizontal >> 4);
+                let rows = (vertical & 0xF) * BLOCK_HEIGHT..((vertical & 0xF) + (vertical >> 4) + 1) * BLOCK_HEIGHT;
+                for row in rows.take_while(|row| *row < SCREEN_HEIGHT) {
+                    for column in columns.clone().take_while(|column| *column < ZONE_COLUMNS) {
+                        self.zone_colors[row * ZONE_COLUMNS + column] = color;
+                    }
+                }
+                self.mark_dirty(self.full_screen());
+            },
+
+            // BXYN: Colour N rows of the zone at VX, VX+1 with VY (CHIP-8X)
+            (0xB, _, _, _) if self.platform == Platform::Chip8X => {
+                let x = nibble2 as usize;
+                let y = nibble3 as usize;
+
+                let column = self.v_reg[x] as usize % SCREEN_WIDTH / ZONE_WIDTH;
+                let top = self.v_reg[(x + 1) % NUM_V] as usize % SCREEN_HEIGHT;
+                let color = self.v_reg[y] & 0x7;
+
+                for row in (top..top + nibble4 as usize).take_while(|row| *row < SCREEN_HEIGHT) {
+                    self.zone_colors[row * ZONE_COLUMNS + column] = color;
+                }
+                self.mark_dirty(self.full_screen());
+            },
+
+            // BBNN: Jump back NN bytes from the next instruction (CHIP-8E)
+            (0xB, 0xB, _, _) if self.platform == Platform::Chip8E => {
+                let nn = op & 0x00FF;
+
+                if nn > self.pc {
+                    self.fault = Some(Fault::JumpOutOfRange { pc: self.pc - 2 });
+                    return;
+                }
+                self.pc = self.pc.wrapping_sub(nn);
+            },
+
+            // BFNN: Jump forward NN bytes from the next instruction (CHIP-8E)
+            (0xB, 0xF, _, _) if self.platform == Platform::Chip8E => {
+                let nn = op & 0x00FF;
+
+                let target = self.pc.wrapping_add(nn);
+                if target < self.pc || target as usize + 1 >= self.mem.len() {
+                    self.fault = Some(Fault::JumpOutOfRange { pc: self.pc - 2 });
+                    return;
+                }
+                self.pc = target;
             },
 
             // BNNN: Jump with offset
             (0xB, _, _, _) => {
                 let mut nnn = op & 0x0FFF;
-                let x = nibble2 as usize;
 
                 if self.cosmac {
                     nnn += self.v_reg[0] as u16;
@@ -370,26 +1054,16 @@ impl Emu {
                 }
 
                 self.pc = nnn;
-                
-                if self.cosmac {
-                    println!("JP V0, {}", nnn);
-                }
-                else {
-                    println!("JP V{}, {}", x, nnn);
-                }
             },
 
             // CXNN: Random
             (0xC, _, _, _) => {
                 let x = nibble2 as usize;
-                let nn = (op & 0x00FF) as u16;
+                let nn = op & 0x00FF;
                 
-                let mut rng = rand::thread_rng();
-                let rand_num: u16 = rng.gen();
+                let rand_num: u16 = self.rng.gen();
 
                 self.v_reg[x] = (nn & rand_num) as u8;
-
-                println!("RND V{}, {}", x, nn);
             },
 
             // DXYN: Display
@@ -397,28 +1071,36 @@ impl Emu {
                 let x_coord = self.v_reg[nibble2 as usize] as u16;
                 let y_coord = self.v_reg[nibble3 as usize] as u16;
 
+                // MegaChip sprites take their size from 03NN and 04NN rather than N
+                if let Some(mega) = self.mega.as_mut() {
+                    let collision = mega.blit(&self.mem, self.i_reg as usize, x_coord as usize, y_coord as usize);
+                    self.v_reg[0xF] = collision as u8;
+                    return;
+                }
+
                 let num_rows = nibble4;
+                let screen_height = self.platform.screen_height();
                 // Keep track if any pixels were flipped
                 let mut flipped = false;
                 // Iterate over each row of our sprite
                 for y_line in 0..num_rows {
                     // Determine which memory address our row's data is stored
-                    let addr = self.i_reg + y_line as u16;
-                    let pixels = self.mem[addr as usize];
-                    // Iterate over each column in our row
-                    for x_line in 0..8 {
-                        // Use a mask to fetch current pixel's bit. Only flip if a 1
-                        if (pixels & (0b1000_0000 >> x_line)) != 0 {
-                            // Sprites should wrap around screen, so apply modulo
-                            let x = (x_coord + x_line) as usize % SCREEN_WIDTH;
-                            let y = (y_coord + y_line) as usize % SCREEN_HEIGHT;
-                            // Get our pixel's index for our 1D screen array
-                            let idx = x + SCREEN_WIDTH * y;
-                            // Check if we're about to flip the pixel and set
-                            flipped |= self.display[idx];
-                            self.display[idx] ^= true;
-                        }
+                    let addr = self.i_reg + y_line as u32;
+                    let pixels = self.mem[addr as usize] as u64;
+                    if pixels == 0 {
+                        continue;
                     }
+                    // Line the byte up with x in a whole screen row. Rotating rather than
+                    // shifting makes sprites wrap around to the left edge
+                    let bits = (pixels << (SCREEN_WIDTH - 8)).rotate_right(x_coord as u32 % SCREEN_WIDTH as u32);
+                    let y = (y_coord + y_line) as usize % screen_height;
+                    flipped |= self.display.xor_word(y, 0, bits);
+
+                    // The leftmost and rightmost pixels flipped, which are further apart
+                    // than the sprite is wide when it wraps
+                    let left = bits.leading_zeros() as usize;
+                    let right = SCREEN_WIDTH - 1 - bits.trailing_zeros() as usize;
+                    self.mark_dirty(DirtyRect { x: left, y, width: right - left + 1, height: 1 });
                 }
                 // Populate VF register
                 if flipped {
@@ -427,7 +1109,12 @@ impl Emu {
                     self.v_reg[0xF] = 0;
                 }
 
-                println!("DRW V{}, V{}, {}", nibble2, nibble3, nibble4);
+                self.emit_draw(DrawEvent::Sprite {
+                    x: x_coord as usize % SCREEN_WIDTH,
+                    y: y_coord as usize % screen_height,
+                    rows: num_rows as usize,
+                    collision: flipped,
+                });
             },
 
             // EX9E: Skip if pressed
@@ -440,8 +1127,6 @@ impl Emu {
                 if key_pressed {
                     self.pc += 2;
                 }
-
-                println!("SKP V{}", x);
             },
 
             // EXA1: Skip if not pressed
@@ -454,8 +1139,33 @@ impl Emu {
                 if !key_pressed {
                     self.pc += 2;
                 }
+            },
+
+            // EXF2: Skip if pressed on the second keypad (CHIP-8X)
+            (0xE, _, 0xF, 2) if self.platform == Platform::Chip8X => {
+                let x = nibble2 as usize;
+                let index = self.v_reg[x] as usize % NUM_KEYS;
+
+                if self.keys2[index] {
+                    self.pc += 2;
+                }
+            },
+
+            // EXF5: Skip if not pressed on the second keypad (CHIP-8X)
+            (0xE, _, 0xF, 5) if self.platform == Platform::Chip8X => {
+                let x = nibble2 as usize;
+                let index = self.v_reg[x] as usize % NUM_KEYS;
+
+                if !self.keys2[index] {
+                    self.pc += 2;
+                }
+            },
 
-                println!("SKNP V{}", x);
+            // FX03: Send VX to output port 3 (CHIP-8E)
+            (0xF, _, 0, 3) if self.platform == Platform::Chip8E => {
+                let x = nibble2 as usize;
+
+                self.port_out = Some(self.v_reg[x]);
             },
 
             // FX07: Set VX to delay timer value
@@ -463,8 +1173,6 @@ impl Emu {
                 let x = nibble2 as usize;
 
                 self.v_reg[x] = self.d_timer;
-
-                println!("LD V{}, DT", x);
             },
 
             // FX0A: Get key
@@ -484,9 +1192,6 @@ impl Emu {
                 if !key_pressed {
                     self.pc -= 2;
                 }
-                else {
-                    println!("LD V{}, K", x);
-                }
             },
 
             // FX15: Set delay timer to VX
@@ -494,16 +1199,35 @@ impl Emu {
                 let x = nibble2 as usize;
 
                 self.d_timer = self.v_reg[x];
-
-                println!("LD DT ,V{}", x);
             }
 
+            // FX1B: Skip VX bytes (CHIP-8E)
+            (0xF, _, 1, 0xB) if self.platform == Platform::Chip8E => {
+                let x = nibble2 as usize;
+
+                self.pc += self.v_reg[x] as u16;
+            },
+
+            // FX4F: Set the delay timer to VX and wait for it to run out (CHIP-8E)
+            (0xF, _, 4, 0xF) if self.platform == Platform::Chip8E => {
+                let x = nibble2 as usize;
+
+                if !self.timer_wait {
+                    self.d_timer = self.v_reg[x];
+                    self.timer_wait = true;
+                }
+                if self.d_timer > 0 {
+                    self.pc -= 2;
+                }
+                else {
+                    self.timer_wait = false;
+                }
+            },
+
             // FX18: Set sound timer to VX
             (0xF, _, 1, 8) => {
                 let x = nibble2 as usize;
                 self.s_timer = self.v_reg[x];
-
-                println!("LD ST ,V{}", x);
             },
 
             // FX1E: Add to index
@@ -511,13 +1235,11 @@ impl Emu {
                 let x = nibble2 as usize;
                 
                 if self.cosmac{
-                    self.i_reg += self.v_reg[x] as u16;
+                    self.i_reg += self.v_reg[x] as u32;
                 }
                 else {
-                    self.i_reg = self.i_reg.wrapping_add(self.v_reg[x] as u16);
+                    self.i_reg = self.i_reg.wrapping_add(self.v_reg[x] as u32);
                 }
-
-                println!("ADD I, V{}", x);
             },
 
             // FX29: Font character
@@ -525,9 +1247,15 @@ impl Emu {
                 let x = nibble2 as usize;
                 let hex_char = self.v_reg[x] & 0x0F;
                 
-                self.i_reg = (hex_char as u16) * 5;
+                self.i_reg = self.font_addr as u32 + (hex_char as u32) * 5;
+            },
+
+            // FX30: Big font character, 10 rows tall
+            (0xF, _, 3, 0) => {
+                let x = nibble2 as usize;
+                let hex_char = self.v_reg[x] & 0x0F;
 
-                println!("LD F, V{}", x);
+                self.i_reg = self.font_addr as u32 + font::SMALL_FONT_SIZE as u32 + (hex_char as u32) * 10;
             },
 
 
@@ -544,27 +1272,27 @@ impl Emu {
                 self.mem[self.i_reg as usize] = hundreds;
                 self.mem[(self.i_reg + 1) as usize] = tens;
                 self.mem[(self.i_reg + 2) as usize] = ones;
-
-                println!("LD B, V{}", x);
+                self.invalidate(self.i_reg as usize, 3);
             },
 
             // FX55: Store memory
             (0xF, _, 5, 5) => {
                 let x = nibble2 as usize;
+                // I moves along with i in the COSMAC loop, so it writes every other byte
+                let start = self.i_reg as usize;
+                self.invalidate(start, 2 * (x + 1));
 
                 if self.cosmac {
                     for i in 0..(x + 1) {
-                        self.mem[((self.i_reg as usize) + i) as usize] = self.v_reg[x];
+                        self.mem[(self.i_reg as usize) + i] = self.v_reg[x];
                         self.i_reg += 1;
                     }
                 }
                 else {
                     for i in 0..(x + 1) {
-                        self.mem[((self.i_reg as usize) + i) as usize] = self.v_reg[x];
+                        self.mem[(self.i_reg as usize) + i] = self.v_reg[x];
                     }
                 }
-
-                println!("LD [I], V{}", x);
             },
 
             // FX65: Load memory
@@ -573,35 +1301,609 @@ impl Emu {
 
                 if self.cosmac {
                     for i in 0..(x + 1) {
-                        self.v_reg[x] = self.mem[((self.i_reg as usize) + i) as usize]; 
+                        self.v_reg[x] = self.mem[(self.i_reg as usize) + i]; 
                         self.i_reg += 1;
                     }
                 }
                 else {
                     for i in 0..(x + 1) {
-                        self.v_reg[x] = self.mem[((self.i_reg as usize) + i) as usize]; 
+                        self.v_reg[x] = self.mem[(self.i_reg as usize) + i]; 
                     }
                 }
+            },
+
+            // FXE3 and FXE7: Read input port 3 into VX. There's no strobe for FXE3 to wait
+            // for, so both read straight away (CHIP-8E)
+            (0xF, _, 0xE, 3) | (0xF, _, 0xE, 7) if self.platform == Platform::Chip8E => {
+                let x = nibble2 as usize;
+
+                self.v_reg[x] = self.port_in;
+            },
 
-                println!("LD V{}, [I]", x);
+            // FXF8: Send VX to the output port, which sets the VP-595's tone (CHIP-8X)
+            (0xF, _, 0xF, 8) if self.platform == Platform::Chip8X => {
+                let x = nibble2 as usize;
+
+                self.port_out = Some(self.v_reg[x]);
+            },
+
+            // FXFB: Read the input port into VX (CHIP-8X)
+            (0xF, _, 0xF, 0xB) if self.platform == Platform::Chip8X => {
+                let x = nibble2 as usize;
+
+                self.v_reg[x] = self.port_in;
+            },
+
+            // 0NNN: Run 1802 machine code at NNN, only possible on the COSMAC VIP
+            (0, _, _, _) if self.cosmac => {
+                let nnn = op & 0x0FFF;
+
+                self.call_machine_code(nnn);
+            },
+
+            // Nothing this platform knows, which would have sent a real interpreter off into the weeds
+            (_, _, _, _) => self.fault = Some(Fault::UnknownOpcode { pc: self.pc - 2, op }),
+        }
+    }
+
+    // The same as execute for the instructions the dispatch cache decodes
+    fn execute_decoded(&mut self, inst: Inst) {
+        match inst {
+            Inst::Jump(nnn) => self.pc = nnn,
+            Inst::Call(nnn) => {
+                if self.sp >= self.stack_depth {
+                    self.fault = Some(Fault::StackOverflow { pc: self.pc - 2 });
+                    return;
+                }
+                self.stack[self.sp] = self.pc;
+                self.sp += 1;
+                self.pc = nnn;
+            },
+            Inst::SkipIfEqual { x, nn } => {
+                if self.v_reg[x as usize] == nn {
+                    self.pc += 2;
+                }
+            },
+            Inst::SkipIfNotEqual { x, nn } => {
+                if self.v_reg[x as usize] != nn {
+                    self.pc += 2;
+                }
+            },
+            Inst::SkipIfEqualReg { x, y } => {
+                if self.v_reg[x as usize] == self.v_reg[y as usize] {
+                    self.pc += 2;
+                }
+            },
+            Inst::SkipIfNotEqualReg { x, y } => {
+                if self.v_reg[x as usize] != self.v_reg[y as usize] {
+                    self.pc += 2;
+                }
+            },
+            Inst::Set { x, nn } => self.v_reg[x as usize] = nn,
+            Inst::Add { x, nn } => self.v_reg[x as usize] = self.v_reg[x as usize].wrapping_add(nn),
+            Inst::Copy { x, y } => self.v_reg[x as usize] = self.v_reg[y as usize],
+            Inst::Or { x, y } => self.v_reg[x as usize] |= self.v_reg[y as usize],
+            Inst::And { x, y } => self.v_reg[x as usize] &= self.v_reg[y as usize],
+            Inst::Xor { x, y } => self.v_reg[x as usize] ^= self.v_reg[y as usize],
+            Inst::AddReg { x, y } => {
+                let (sum, overflow) = self.v_reg[x as usize].overflowing_add(self.v_reg[y as usize]);
+                self.v_reg[0xF] = overflow as u8;
+                self.v_reg[x as usize] = sum;
+            },
+            Inst::Sub { x, y } => {
+                let (diff, underflow) = self.v_reg[x as usize].overflowing_sub(self.v_reg[y as usize]);
+                self.v_reg[0xF] = !underflow as u8;
+                self.v_reg[x as usize] = diff;
+            },
+            Inst::SubReverse { x, y } => {
+                let (diff, underflow) = self.v_reg[y as usize].overflowing_sub(self.v_reg[x as usize]);
+                self.v_reg[0xF] = !underflow as u8;
+                self.v_reg[x as usize] = diff;
             },
+            Inst::SetIndex(nnn) => self.i_reg = nnn as u32,
+            Inst::GetDelay { x } => self.v_reg[x as usize] = self.d_timer,
+            Inst::SetDelay { x } => self.d_timer = self.v_reg[x as usize],
+            Inst::SetSound { x } => self.s_timer = self.v_reg[x as usize],
+            Inst::Other => unreachable!("tick runs undecoded instructions with execute"),
+        }
+    }
+
+    // Set up the 1802 the way the VIP's interpreter leaves it for a 0NNN call, with the
+    // V registers and display where machine code expects them in memory, then run until
+    // the code hands back with D4 (SEP R4) and copy everything back out
+    fn call_machine_code(&mut self, addr: u16) {
+        // The VIP's display memory only holds a 64x32 screen, up to the end of its 4K
+        if self.get_screen_size() != (SCREEN_WIDTH, SCREEN_HEIGHT) {
+            self.fault = Some(Fault::MachineCodeUnsupported { pc: self.pc - 2 });
+            return;
+        }
+
+        self.mem[VIP_V_ADDR..VIP_V_ADDR + NUM_V].copy_from_slice(&self.v_reg);
+        for (bytes, row) in self.mem[VIP_DISPLAY_ADDR..MEM_SIZE].chunks_mut(8).zip(self.display.rows()) {
+            bytes.copy_from_slice(&row.to_be_bytes());
+        }
+
+        let mut cpu = Cdp1802::new();
+        cpu.r[2] = VIP_STACK_ADDR;
+        cpu.x = 2;
+        cpu.r[3] = addr;
+        cpu.p = 3;
+        cpu.r[5] = self.pc;
+        cpu.r[8] = u16::from_be_bytes([self.d_timer, self.s_timer]);
+        cpu.r[0xA] = self.i_reg as u16;
+        cpu.r[0xB] = VIP_DISPLAY_ADDR as u16;
+
+        let mut cycles = 0;
+        while cpu.p != 4 {
+            if cycles > MACHINE_CODE_LIMIT {
+                self.fault = Some(Fault::MachineCodeHung { pc: self.pc - 2 });
+                return;
+            }
+            cycles += cpu.step(&mut self.mem, &self.keys);
+        }
+        if self.vip_timing {
+            self.cycle_budget -= cycles as i32;
+        }
+
+        self.v_reg.copy_from_slice(&self.mem[VIP_V_ADDR..VIP_V_ADDR + NUM_V]);
+        // A row is a single word on a 64x32 screen
+        let mut changed = false;
+        for (y, bytes) in self.mem[VIP_DISPLAY_ADDR..MEM_SIZE].chunks_exact(8).enumerate() {
+            let word = u64::from_be_bytes(bytes.try_into().unwrap());
+            changed |= self.display.rows()[y] != word;
+            self.display.set_word(y, 0, word);
+        }
+        if changed {
+            self.mark_dirty(self.full_screen());
+        }
+
+        // The machine code could have written anywhere
+        self.forget_decoded();
+
+        self.pc = cpu.r[5];
+        [self.d_timer, self.s_timer] = cpu.r[8].to_be_bytes();
+        self.i_reg = cpu.r[0xA] as u32;
+    }
+
+    // In MegaChip mode this is also what puts the finished frame on screen
+    fn clear_screen(&mut self) {
+        if let Some(mega) = self.mega.as_mut() {
+            mega.show();
+            self.display.load_pixels(mega.frame().iter().map(|color| *color != 0));
+            self.mark_dirty(self.full_screen());
+            self.emit_draw(DrawEvent::Frame);
+        }
+        else if self.display.any() {
+            self.display.clear();
+            self.mark_dirty(self.full_screen());
+            self.emit_draw(DrawEvent::Clear);
+        }
+    }
+
+    // A bool per pixel, row by row, only as many as the screen has right now. This is built
+    // from get_packed_display each time, so frontends looking at a few pixels should use
+    // get_pixel instead
+    pub fn get_display(&self) -> Vec<bool> {
+        self.display.pixels().collect()
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> bool {
+        self.display.get(x, y)
+    }
 
-            (_, _, _, _) => unimplemented!("Unimplemented opcode: {}", op),
+    // The same pixels a bit each, one u64 per 64 pixels of a row with the leftmost in the
+    // top bit. Rows are get_screen_size().0 / 64 words long
+    pub fn get_packed_display(&self) -> &[u64] {
+        self.display.rows()
+    }
+
+    fn blank_display(&self) -> Framebuffer {
+        let (width, height) = self.get_screen_size();
+        Framebuffer::new(width, height)
+    }
+
+    // Returns the part of the display that changed since the last call, if any
+    pub fn take_dirty(&mut self) -> Option<DirtyRect> {
+        self.dirty_rect.take()
+    }
+
+    pub fn set_draw_hook<F>(&mut self, hook: F)
+    where
+        F: FnMut(DrawEvent) + Send + 'static
+    {
+        self.draw_hook = Some(Box::new(hook));
+    }
+
+    pub fn clear_draw_hook(&mut self) {
+        self.draw_hook = None;
+    }
+
+    fn full_screen(&self) -> DirtyRect {
+        let (width, height) = self.get_screen_size();
+        DirtyRect { x: 0, y: 0, width, height }
+    }
+
+    fn mark_dirty(&mut self, rect: DirtyRect) {
+        self.dirty_rect = match self.dirty_rect {
+            Some(dirty) => Some(dirty.union(&rect)),
+            None => Some(rect),
+        };
+    }
+
+    fn emit_draw(&mut self, event: DrawEvent) {
+        if let Some(hook) = self.draw_hook.as_mut() {
+            hook(event);
+        }
+    }
+
+    pub fn get_v_reg(&self) -> &[u8] {
+        &self.v_reg
+    }
+
+    pub fn get_i_reg(&self) -> u32 {
+        self.i_reg
+    }
+
+    pub fn get_pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn get_stack(&self) -> &[u16] {
+        &self.stack[..self.sp]
+    }
+
+    pub fn get_stack_depth(&self) -> usize {
+        self.stack_depth
+    }
+
+    pub fn get_fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    pub fn get_timers(&self) -> (u8, u8) {
+        (self.d_timer, self.s_timer)
+    }
+
+    pub fn get_mem(&self) -> &[u8] {
+        &self.mem
+    }
+
+    pub fn get_keys(&self) -> &[bool] {
+        &self.keys
+    }
+
+    // Setters for debuggers and cheats to poke at the machine between ticks
+
+    pub fn set_v_reg(&mut self, index: usize, value: u8) {
+        self.v_reg[index] = value;
+    }
+
+    pub fn set_i_reg(&mut self, value: u32) {
+        self.i_reg = value;
+    }
+
+    // Leaves PC alone and returns false if there's no whole instruction at value
+    pub fn set_pc(&mut self, value: u16) -> bool {
+        if value as usize > self.mem.len() - 2 {
+            return false;
+        }
+        self.pc = value;
+        true
+    }
+
+    pub fn set_timers(&mut self, delay: u8, sound: u8) {
+        self.d_timer = delay;
+        self.s_timer = sound;
+    }
+
+    pub fn set_mem(&mut self, addr: usize, value: u8) {
+        self.mem[addr] = value;
+        self.invalidate(addr, 1);
+    }
+
+    pub fn get_platform(&self) -> Platform {
+        self.platform
+    }
+
+    // CHIP-8X colours as VP-590 colour numbers: the background, and the foreground of each
+    // ZONE_WIDTH pixel zone, row by row. None on other platforms
+    pub fn get_colors(&self) -> Option<(u8, &[u8])> {
+        match self.platform {
+            Platform::Chip8X => Some((BACKGROUND_COLORS[self.background], &self.zone_colors)),
+            Platform::Chip8 | Platform::Chip8Hires | Platform::MegaChip | Platform::Eti660 | Platform::Dream6800
+                | Platform::Chip8E => None,
         }
     }
 
-    pub fn get_display(&self) -> &[bool] {
-        &self.display
+    // The MegaChip screen as 0xRRGGBB, which replaces get_display while in MegaChip mode
+    pub fn get_frame(&self) -> Option<&[u32]> {
+        self.mega.as_ref().map(|mega| mega.frame())
+    }
+
+    // The last sampled sound started or stopped since this was last called
+    pub fn take_sound(&mut self) -> Option<SoundEvent> {
+        self.mega.as_mut().and_then(|mega| mega.take_sound())
+    }
+
+    // What FXF8, or CHIP-8E's FX03, last sent out, if it's been used
+    pub fn get_port_out(&self) -> Option<u8> {
+        self.port_out
+    }
+
+    pub fn set_port_in(&mut self, value: u8) {
+        self.port_in = value;
     }
 
-    pub fn load(&mut self, data: &[u8]) {
-        let start = START_ADDR as usize;
-        let end = (START_ADDR as usize) + data.len();
+    pub fn get_screen_size(&self) -> (usize, usize) {
+        match self.mega {
+            Some(_) => (megachip::WIDTH, megachip::HEIGHT),
+            None => (SCREEN_WIDTH, self.platform.screen_height()),
+        }
+    }
+
+    // Hi-res programs without their bootstrap jump get one, with the program after it
+    pub fn load(&mut self, data: &[u8]) -> Result<(), LoadError> {
+        let start_addr = self.platform.start_addr() as usize;
+        let start = self.platform.load_addr(data) as usize;
+        let add_bootstrap = start != start_addr;
+
+        let max = self.mem.len() - start;
+        if data.len() > max {
+            return Err(LoadError::TooLarge { size: data.len(), max });
+        }
+
+        if add_bootstrap {
+            self.mem[start_addr..start_addr + HIRES_BOOTSTRAP.len()].copy_from_slice(&HIRES_BOOTSTRAP);
+        }
+        let end = start + data.len();
         self.mem[start..end].copy_from_slice(data);
+        self.invalidate(start_addr, end - start_addr);
+
+        Ok(())
     }
 
     pub fn keypress(&mut self, index: usize, pressed: bool) {
+        // The DREAM 6800 only ever sees the last key pressed
+        if pressed && self.platform == Platform::Dream6800 {
+            self.keys = [false; NUM_KEYS];
+        }
         self.keys[index] = pressed;
     }
 
-}
\ No newline at end of file
+    // Keys on CHIP-8X's second keypad
+    pub fn keypress2(&mut self, index: usize, pressed: bool) {
+        self.keys2[index] = pressed;
+    }
+
+    // Size in bytes of everything save_state writes, which only depends on the platform
+    pub fn state_size(&self) -> usize {
+        let mega_size = match self.platform {
+            Platform::MegaChip => 1 + megachip::STATE_SIZE,
+            Platform::Chip8 | Platform::Chip8X | Platform::Chip8Hires | Platform::Eti660 | Platform::Dream6800
+                | Platform::Chip8E => 0,
+        };
+
+        1 + self.platform.mem_size() + self.platform.display_len() + 2 + 4 + 1 + STACK_SIZE * 2 + 2 + NUM_V + NUM_KEYS + 1
+            + 1 + 2 + 1 + ZONE_COLUMNS * SCREEN_HEIGHT + 2 + 1 + NUM_KEYS + 1 + mega_size
+    }
+
+    // Snapshot the machine. The random number generator isn't included
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Vec::with_capacity(self.state_size());
+
+        state.push(STATE_VERSION);
+        state.extend_from_slice(&self.mem);
+        // Padded out to the biggest screen the platform has, so the size never changes
+        state.extend(self.display.pixels().map(|pixel| pixel as u8));
+        state.resize(state.len() + self.platform.display_len() - self.display.pixel_count(), 0);
+        state.extend_from_slice(&self.pc.to_be_bytes());
+        state.extend_from_slice(&self.i_reg.to_be_bytes());
+
+        state.push(self.sp as u8);
+        for addr in &self.stack {
+            state.extend_from_slice(&addr.to_be_bytes());
+        }
+
+        state.push(self.d_timer);
+        state.push(self.s_timer);
+        state.extend_from_slice(&self.v_reg);
+        state.extend(self.keys.iter().map(|key| *key as u8));
+        state.push(self.cosmac as u8);
+        state.push(self.platform as u8);
+        state.extend_from_slice(&self.font_addr.to_be_bytes());
+
+        state.push(self.background as u8);
+        state.extend_from_slice(&self.zone_colors);
+        // Whether FXF8 has been used, then its value
+        state.push(self.port_out.is_some() as u8);
+        state.push(self.port_out.unwrap_or(0));
+        state.push(self.timer_wait as u8);
+        state.extend(self.keys2.iter().map(|key| *key as u8));
+        state.push(self.port_in);
+
+        // Whether MegaChip mode is on, then its state, left blank when it's off
+        if self.platform == Platform::MegaChip {
+            state.push(self.mega.is_some() as u8);
+            match &self.mega {
+                Some(mega) => mega.save(&mut state),
+                None => state.resize(state.len() + megachip::STATE_SIZE, 0),
+            }
+        }
+
+        state
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        if state.len() != self.state_size() {
+            return Err(StateError::WrongSize);
+        }
+        if state[0] != STATE_VERSION {
+            return Err(StateError::WrongVersion(state[0]));
+        }
+        // Checked before anything is overwritten
+        let regs = 1 + self.platform.mem_size() + self.platform.display_len();
+        let pc = u16::from_be_bytes([state[regs], state[regs + 1]]) as usize;
+        let sp = state[regs + 2 + 4] as usize;
+        if pc + 1 >= self.platform.mem_size() || sp > self.stack_depth {
+            return Err(StateError::Corrupt);
+        }
+
+        let mut pos = 1;
+        let mut take = |len: usize| {
+            let bytes = &state[pos..pos + len];
+            pos += len;
+            bytes
+        };
+
+        self.mem.copy_from_slice(take(self.platform.mem_size()));
+        // Its size depends on whether MegaChip mode is on, which comes later
+        let display = take(self.platform.display_len());
+        self.pc = u16::from_be_bytes([take(1)[0], take(1)[0]]);
+        let i_reg = take(4);
+        self.i_reg = u32::from_be_bytes([i_reg[0], i_reg[1], i_reg[2], i_reg[3]]);
+
+        self.sp = take(1)[0] as usize;
+        for (addr, bytes) in self.stack.iter_mut().zip(take(STACK_SIZE * 2).chunks(2)) {
+            *addr = u16::from_be_bytes([bytes[0], bytes[1]]);
+        }
+
+        self.d_timer = take(1)[0];
+        self.s_timer = take(1)[0];
+        self.v_reg.copy_from_slice(take(NUM_V));
+        for (key, byte) in self.keys.iter_mut().zip(take(NUM_KEYS)) {
+            *key = *byte != 0;
+        }
+        self.cosmac = take(1)[0] != 0;
+        // The size check has already ruled out platforms with different memory or screens
+        self.platform = match take(1)[0] {
+            1 => Platform::Chip8X,
+            2 => Platform::Chip8Hires,
+            3 => Platform::MegaChip,
+            4 => Platform::Eti660,
+            5 => Platform::Dream6800,
+            6 => Platform::Chip8E,
+            _ => Platform::Chip8,
+        };
+        let font_addr = take(2);
+        let font_addr = u16::from_be_bytes([font_addr[0], font_addr[1]]);
+        self.font_addr = if self.platform.font_fits(font_addr) { font_addr } else { FONT_ADDR };
+
+        self.background = take(1)[0] as usize % BACKGROUND_COLORS.len();
+        for (color, byte) in self.zone_colors.iter_mut().zip(take(ZONE_COLUMNS * SCREEN_HEIGHT)) {
+            *color = byte & 0x7;
+        }
+        let port_used = take(1)[0] != 0;
+        let port_out = take(1)[0];
+        self.port_out = if port_used { Some(port_out) } else { None };
+        self.timer_wait = take(1)[0] != 0;
+        for (key, byte) in self.keys2.iter_mut().zip(take(NUM_KEYS)) {
+            *key = *byte != 0;
+        }
+        self.port_in = take(1)[0];
+
+        if self.platform == Platform::MegaChip {
+            let mega_on = take(1)[0] != 0;
+            let mega_state = take(megachip::STATE_SIZE);
+            self.mega = if mega_on {
+                let mut mega = Box::<MegaChip>::default();
+                mega.load(mega_state);
+                Some(mega)
+            }
+            else {
+                None
+            };
+        }
+
+        self.display = self.blank_display();
+        self.display.load_pixels(display.iter().map(|byte| *byte != 0));
+
+        self.cycle_budget = 0;
+        self.fault = None;
+        self.dirty_rect = Some(self.full_screen());
+        self.forget_decoded();
+
+        Ok(())
+    }
+
+}
+
+// Machine cycles the VIP's interpreter takes over op, counting its fetch and decode. vx is
+// VX from before op ran, and pc and next_pc are where op was and where it went on to.
+// Machine code run by 0NNN is charged separately
+fn vip_cycles(op: u16, vx: u8, pc: u16, next_pc: u16) -> u32 {
+    let x = (op & 0x0F00) >> 8;
+    let n = (op & 0x000F) as u32;
+    // Skips take the extra cycles of stepping over the next instruction
+    let skip = if next_pc == pc.wrapping_add(4) { 4 } else { 0 };
+
+    match (op >> 12, x, op & 0x00FF) {
+        // 00E0 clears the display a byte at a time
+        (0, 0, 0xE0) => 24 + 3078,
+        (0, 0, 0xEE) => 10,
+        // The 1802 code that 0NNN runs is counted by the call itself
+        (0, _, _) => 26,
+        (1, _, _) => 12,
+        (2, _, _) => 26,
+        (3, _, _) | (4, _, _) => 10 + skip,
+        (5, _, _) | (9, _, _) => 14 + skip,
+        (6, _, _) => 6,
+        (7, _, _) => 10,
+        (8, _, nn) if nn & 0x0F == 0 => 12,
+        // The other 8XYN instructions are built in RAM and run as 1802 code
+        (8, _, _) => 44,
+        (0xA, _, _) => 12,
+        // One more long branch when the jump lands on a different page
+        (0xB, _, _) => if next_pc >> 8 != (op & 0x0FFF) >> 8 { 24 } else { 22 },
+        (0xC, _, _) => 36,
+        // Each sprite row is shifted into place a bit at a time, and one that isn't byte
+        // aligned has a second display byte to write
+        (0xD, _, _) => {
+            let shift = (vx & 7) as u32;
+            let row = if shift == 0 { 34 } else { 46 + 8 * shift };
+            68 + n * row
+        },
+        (0xE, _, _) => 14 + skip,
+        (0xF, _, 0x0A) => 18,
+        // The decimal digits come from repeated subtraction
+        (0xF, _, 0x33) => {
+            let digits = (vx / 100 + vx / 10 % 10 + vx % 10) as u32;
+            80 + 16 * digits
+        },
+        (0xF, _, 0x55) | (0xF, _, 0x65) => 14 + 14 * (x as u32 + 1),
+        (0xF, _, 0x1E) | (0xF, _, 0x29) => 16,
+        _ => 10,
+    }
+}
+
+// What compiled blocks call for instructions they don't do themselves. A panic is caught
+// and passed back to run_jit, so a bad instruction panics the same way it would interpreted
+#[cfg(feature = "jit")]
+extern "C" fn jit_execute(emu: *mut Emu, op: u32) -> u8 {
+    // Safety: blocks are only run by run_jit, which passes itself
+    let emu = unsafe { &mut *emu };
+    match panic::catch_unwind(panic::AssertUnwindSafe(|| emu.execute(op as u16))) {
+        Ok(()) => 1,
+        Err(payload) => {
+            if let Some(jit) = emu.jit.as_mut() {
+                jit.set_panic(payload);
+            }
+            0
+        },
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn initial_seed() -> u64 {
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_nanos() as u64)
+        .unwrap_or(DEFAULT_SEED)
+}
+
+// wasm32-unknown-unknown has no system clock, so hosts should call set_seed
+#[cfg(target_arch = "wasm32")]
+fn initial_seed() -> u64 {
+    DEFAULT_SEED
+}
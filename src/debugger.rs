@@ -0,0 +1,590 @@
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+use sdl2::VideoSubsystem;
+
+use yachip8emu::cheat::{Compare, Search};
+use yachip8emu::cpu::{Emu, Platform};
+use yachip8emu::disasm;
+
+use crate::cheats::Cheats;
+use crate::text;
+use crate::video::Palette;
+
+const SCALE: u32 = 2;
+// Window size in character cells
+const COLUMNS: u32 = 81;
+const ROWS: u32 = 37;
+
+// Where each panel starts, in character cells
+const REG_COLUMN: i32 = 1;
+const DISASM_COLUMN: i32 = 20;
+const HEX_COLUMN: i32 = 48;
+const TOP_ROW: i32 = 2;
+
+const NUM_REG_FIELDS: usize = 20;
+const DISASM_LINES: usize = 21;
+// Instructions shown before PC in the disassembly
+const DISASM_BEFORE: u16 = 8;
+const BYTES_PER_ROW: usize = 8;
+const MEM_ROWS: usize = 32;
+const STACK_PER_ROW: usize = 4;
+const CHEAT_ROWS: usize = ROWS as usize - TOP_ROW as usize - 2;
+// Searches with more results than this only show how many there are until narrowed down
+const MAX_LISTED_RESULTS: usize = 10_000;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Panel {
+    Registers,
+    Memory,
+    // Takes the place of the disassembly while it has focus
+    Cheats,
+}
+
+impl Panel {
+    fn next(&self) -> Panel {
+        match self {
+            Panel::Registers => Panel::Memory,
+            Panel::Memory => Panel::Cheats,
+            Panel::Cheats => Panel::Registers,
+        }
+    }
+}
+
+// Something in the machine that can be edited from the debugger
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    V(usize),
+    I,
+    Pc,
+    Delay,
+    Sound,
+    Mem(usize),
+}
+
+impl Field {
+    // Registers in the order the panel lists them
+    fn register(index: usize) -> Field {
+        match index {
+            0..=15 => Field::V(index),
+            16 => Field::I,
+            17 => Field::Pc,
+            18 => Field::Delay,
+            _ => Field::Sound,
+        }
+    }
+
+    // Hex digits it takes to type a whole value. MegaChip's I is 24 bits and its PC can go
+    // past 0xFFF
+    fn digits(&self, emu: &Emu) -> usize {
+        let mega = emu.get_platform() == Platform::MegaChip;
+        match self {
+            Field::I if mega => 6,
+            Field::Pc if mega => 4,
+            Field::I | Field::Pc => 3,
+            _ => 2,
+        }
+    }
+
+    fn read(&self, emu: &Emu) -> u32 {
+        let (d_timer, s_timer) = emu.get_timers();
+        match *self {
+            Field::V(x) => emu.get_v_reg()[x] as u32,
+            Field::I => emu.get_i_reg(),
+            Field::Pc => emu.get_pc() as u32,
+            Field::Delay => d_timer as u32,
+            Field::Sound => s_timer as u32,
+            Field::Mem(addr) => emu.get_mem()[addr] as u32,
+        }
+    }
+
+    fn write(&self, emu: &mut Emu, value: u32) {
+        let (d_timer, s_timer) = emu.get_timers();
+        match *self {
+            Field::V(x) => emu.set_v_reg(x, value as u8),
+            Field::I => emu.set_i_reg(value),
+            // An address past the end of memory is dropped rather than crashing on the next fetch
+            Field::Pc => {
+                emu.set_pc(value as u16);
+            },
+            Field::Delay => emu.set_timers(value as u8, s_timer),
+            Field::Sound => emu.set_timers(d_timer, value as u8),
+            Field::Mem(addr) => emu.set_mem(addr, value as u8),
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            Field::V(x) => format!("V{:X}", x),
+            Field::I => String::from("I "),
+            Field::Pc => String::from("PC"),
+            Field::Delay => String::from("DT"),
+            Field::Sound => String::from("ST"),
+            Field::Mem(addr) => format!("{:#05X}", addr),
+        }
+    }
+}
+
+// A second window showing the machine's insides, with registers and memory editable in place
+pub struct Debugger {
+    canvas: Canvas<Window>,
+    panel: Panel,
+    reg_cursor: usize,
+    mem_cursor: usize,
+    // First row shown in the hex view
+    mem_scroll: usize,
+    cheat_cursor: usize,
+    cheat_scroll: usize,
+    // Hex digits typed so far for the selected field
+    edit: String,
+}
+
+impl Debugger {
+    pub fn open(video_subsys: &VideoSubsystem) -> Result<Debugger, String> {
+        let width = COLUMNS * text::ADVANCE * SCALE;
+        let height = ROWS * text::LINE_HEIGHT * SCALE;
+        let window = video_subsys
+            .window("yachip8emu debugger", width, height)
+            .build()
+            .map_err(|e| e.to_string())?;
+        // No vsync here, or waiting on both windows would halve the frame rate
+        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+
+        Ok(Debugger {
+            canvas,
+            panel: Panel::Registers,
+            reg_cursor: 0,
+            mem_cursor: 0x200,
+            mem_scroll: 0x200 / BYTES_PER_ROW,
+            cheat_cursor: 0,
+            cheat_scroll: 0,
+            edit: String::new(),
+        })
+    }
+
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    fn selected(&self) -> Field {
+        if self.panel == Panel::Memory {
+            Field::Mem(self.mem_cursor)
+        }
+        else {
+            Field::register(self.reg_cursor)
+        }
+    }
+
+    // Handle an event sent to the debugger window
+    pub fn handle_event(&mut self, event: &Event, emu: &mut Emu, cheats: &mut Cheats) {
+        let key = match event {
+            Event::KeyDown{keycode: Some(key), ..} => *key,
+            _ => return,
+        };
+
+        if key == Keycode::Tab {
+            self.panel = self.panel.next();
+            self.edit.clear();
+        }
+        else if self.panel == Panel::Cheats {
+            self.cheat_key(key, emu, cheats);
+        }
+        else {
+            self.edit_key(key, emu);
+        }
+    }
+
+    // Keys for the register and memory panels
+    fn edit_key(&mut self, key: Keycode, emu: &mut Emu) {
+        let mem_size = emu.get_mem().len();
+        let memory_focused = self.panel == Panel::Memory;
+
+        match key {
+            Keycode::Escape => self.edit.clear(),
+            Keycode::Backspace => {
+                self.edit.pop();
+            },
+            Keycode::Return if !self.edit.is_empty() => self.commit(emu),
+            Keycode::Up => self.move_cursor(-1, -(BYTES_PER_ROW as isize), mem_size),
+            Keycode::Down => self.move_cursor(1, BYTES_PER_ROW as isize, mem_size),
+            Keycode::Left => self.move_cursor(0, -1, mem_size),
+            Keycode::Right => self.move_cursor(0, 1, mem_size),
+            Keycode::PageUp => self.move_cursor(0, -((BYTES_PER_ROW * MEM_ROWS) as isize), mem_size),
+            Keycode::PageDown => self.move_cursor(0, (BYTES_PER_ROW * MEM_ROWS) as isize, mem_size),
+            // Jump the hex view to where I or PC point
+            Keycode::I if memory_focused => {
+                self.mem_cursor = emu.get_i_reg() as usize % mem_size;
+                self.edit.clear();
+            },
+            Keycode::P if memory_focused => {
+                self.mem_cursor = emu.get_pc() as usize % mem_size;
+                self.edit.clear();
+            },
+            _ => {
+                if let Some(digit) = hex_digit(key) {
+                    self.edit.push(digit);
+                    if self.edit.len() == self.selected().digits(emu) {
+                        self.commit(emu);
+                    }
+                }
+            }
+        }
+    }
+
+    // Keys for the cheats panel
+    fn cheat_key(&mut self, key: Keycode, emu: &Emu, cheats: &mut Cheats) {
+        let mem = emu.get_mem();
+        let mut compare = None;
+
+        match key {
+            Keycode::N => {
+                cheats.search = Some(Search::new(mem));
+                self.cheat_cursor = 0;
+            },
+            Keycode::X => compare = Some(Compare::Changed),
+            Keycode::Z => compare = Some(Compare::Unchanged),
+            Keycode::Plus | Keycode::Equals | Keycode::KpPlus => compare = Some(Compare::Increased),
+            Keycode::Minus | Keycode::KpMinus => compare = Some(Compare::Decreased),
+            Keycode::Return if !self.edit.is_empty() => {
+                compare = Some(Compare::Equal(u8::from_str_radix(&self.edit, 16).unwrap()));
+            },
+            Keycode::Escape => self.edit.clear(),
+            Keycode::Backspace => {
+                self.edit.pop();
+            },
+            Keycode::Up => self.cheat_cursor = self.cheat_cursor.saturating_sub(1),
+            Keycode::Down => self.cheat_cursor += 1,
+            Keycode::PageUp => self.cheat_cursor = self.cheat_cursor.saturating_sub(CHEAT_ROWS),
+            Keycode::PageDown => self.cheat_cursor += CHEAT_ROWS,
+            Keycode::Space => {
+                if let Some(addr) = self.cheat_target(cheats) {
+                    let frozen_before = cheats.list.get().len();
+                    let value = mem.get(addr).copied().unwrap_or(0);
+                    if let Err(e) = cheats.toggle(addr as u16, value) {
+                        eprintln!("error: {}", e);
+                    }
+                    // Stay on the same search result as the frozen list above it changes length
+                    if self.cheat_cursor >= frozen_before {
+                        self.cheat_cursor = (self.cheat_cursor + cheats.list.get().len()).saturating_sub(frozen_before);
+                    }
+                }
+            },
+            _ => {
+                if let Some(digit) = hex_digit(key) {
+                    self.edit.push(digit);
+                    if self.edit.len() == 2 {
+                        compare = Some(Compare::Equal(u8::from_str_radix(&self.edit, 16).unwrap()));
+                    }
+                }
+            }
+        }
+
+        if let Some(compare) = compare {
+            if let Some(search) = cheats.search.as_mut() {
+                search.refine(mem, compare);
+                self.cheat_cursor = cheats.list.get().len();
+            }
+            self.edit.clear();
+        }
+
+        let count = cheats.list.get().len() + listed_results(cheats);
+        self.cheat_cursor = self.cheat_cursor.min(count.saturating_sub(1));
+    }
+
+    // Address of the frozen cheat or search result under the cursor
+    fn cheat_target(&self, cheats: &Cheats) -> Option<usize> {
+        let frozen = cheats.list.get();
+        if self.cheat_cursor < frozen.len() {
+            return Some(frozen[self.cheat_cursor].addr as usize);
+        }
+        if self.cheat_cursor - frozen.len() >= listed_results(cheats) {
+            return None;
+        }
+        cheats.search.as_ref()?.candidates(self.cheat_cursor - frozen.len()).next()
+    }
+
+    fn move_cursor(&mut self, reg_delta: isize, mem_delta: isize, mem_size: usize) {
+        self.edit.clear();
+        if self.panel == Panel::Memory {
+            let last = mem_size as isize - 1;
+            self.mem_cursor = (self.mem_cursor as isize + mem_delta).clamp(0, last) as usize;
+        }
+        else {
+            let last = NUM_REG_FIELDS as isize - 1;
+            self.reg_cursor = (self.reg_cursor as isize + reg_delta).clamp(0, last) as usize;
+        }
+    }
+
+    // Write what's been typed into the selected field, then move on to the next byte when editing memory
+    fn commit(&mut self, emu: &mut Emu) {
+        let field = self.selected();
+        let value = u32::from_str_radix(&self.edit, 16).unwrap();
+        field.write(emu, value);
+        self.edit.clear();
+
+        if self.panel == Panel::Memory {
+            self.mem_cursor = (self.mem_cursor + 1).min(emu.get_mem().len() - 1);
+        }
+    }
+
+    pub fn draw(&mut self, emu: &Emu, cheats: &Cheats, palette: &Palette) {
+        let dim = palette.blend(0.5);
+        let title_color = |focused: bool| if focused { palette.fg } else { dim };
+
+        self.canvas.set_draw_color(palette.bg);
+        self.canvas.clear();
+
+        self.label(REG_COLUMN, 0, "Registers", title_color(self.panel == Panel::Registers));
+        self.label(HEX_COLUMN, 0, "Memory", title_color(self.panel == Panel::Memory));
+
+        self.draw_registers(emu, palette);
+        if self.panel == Panel::Cheats {
+            self.label(DISASM_COLUMN, 0, "Cheats", palette.fg);
+            self.draw_cheats(emu, cheats, palette);
+        }
+        else {
+            self.label(DISASM_COLUMN, 0, "Disassembly", dim);
+            self.draw_disassembly(emu, palette);
+        }
+        self.draw_memory(emu, palette);
+
+        if let Some(fault) = emu.get_fault() {
+            let text = format!("Stopped: {}", fault);
+            self.bar(REG_COLUMN, ROWS as i32 - 2, text.len(), palette.fg);
+            self.label(REG_COLUMN, ROWS as i32 - 2, &text, palette.bg);
+        }
+
+        let help = match self.panel {
+            Panel::Cheats => "N: new search  X/Z: changed/same  +/-: up/down  00-FF: equal  Space: freeze",
+            _ => "Tab: switch panel  Arrows: move  0-F: edit  Enter: set  I/P: go to I/PC",
+        };
+        self.label(REG_COLUMN, ROWS as i32 - 1, help, dim);
+
+        self.canvas.present();
+    }
+
+    fn draw_registers(&mut self, emu: &Emu, palette: &Palette) {
+        for index in 0..NUM_REG_FIELDS {
+            let field = Field::register(index);
+            // Leave a gap between the V registers and the rest
+            let row = TOP_ROW + index as i32 + if index >= 16 { 1 } else { 0 };
+            let selected = self.panel == Panel::Registers && index == self.reg_cursor;
+            let value = self.field_text(field, emu, selected);
+            let line = format!("{} {}", field.label(), value);
+
+            if selected {
+                self.bar(REG_COLUMN, row, line.len(), palette.fg);
+                self.label(REG_COLUMN, row, &line, palette.bg);
+            }
+            else {
+                self.label(REG_COLUMN, row, &line, palette.fg);
+            }
+        }
+
+        let keys_row = TOP_ROW + NUM_REG_FIELDS as i32 + 2;
+        self.label(REG_COLUMN, keys_row, "Keys", palette.fg);
+        for (k, pressed) in emu.get_keys().iter().enumerate() {
+            let col = REG_COLUMN + k as i32;
+            let digit = format!("{:X}", k);
+            if *pressed {
+                self.bar(col, keys_row + 1, 1, palette.fg);
+                self.label(col, keys_row + 1, &digit, palette.bg);
+            }
+            else {
+                self.label(col, keys_row + 1, &digit, palette.blend(0.5));
+            }
+        }
+    }
+
+    fn draw_disassembly(&mut self, emu: &Emu, palette: &Palette) {
+        let mem = emu.get_mem();
+        let pc = emu.get_pc();
+        let start = pc.saturating_sub(DISASM_BEFORE * 2);
+
+        for line in 0..DISASM_LINES {
+            let addr = start as usize + line * 2;
+            if addr + 1 >= mem.len() {
+                break;
+            }
+            let op = u16::from_be_bytes([mem[addr], mem[addr + 1]]);
+            let mut text = format!("{:#05X} {:04X} {}", addr, op, disasm::disassemble(op, emu.get_platform()));
+            text.truncate((HEX_COLUMN - DISASM_COLUMN - 2) as usize);
+
+            let row = TOP_ROW + line as i32;
+            if addr == pc as usize {
+                self.bar(DISASM_COLUMN, row, text.len(), palette.fg);
+                self.label(DISASM_COLUMN, row, &text, palette.bg);
+            }
+            else {
+                self.label(DISASM_COLUMN, row, &text, palette.fg);
+            }
+        }
+
+        let stack_row = TOP_ROW + DISASM_LINES as i32 + 1;
+        let stack = emu.get_stack();
+        let heading = format!("Stack ({}/{})", stack.len(), emu.get_stack_depth());
+        self.label(DISASM_COLUMN, stack_row, &heading, palette.fg);
+        for (i, chunk) in stack.chunks(STACK_PER_ROW).enumerate() {
+            let entries: Vec<_> = chunk.iter().map(|addr| format!("{:#05X}", addr)).collect();
+            self.label(DISASM_COLUMN, stack_row + 1 + i as i32, &entries.join(" "), palette.fg);
+        }
+    }
+
+    fn draw_memory(&mut self, emu: &Emu, palette: &Palette) {
+        let mem = emu.get_mem();
+        let pc = emu.get_pc() as usize;
+        let i_reg = emu.get_i_reg() as usize;
+
+        // Scroll just far enough to keep the cursor in view
+        let cursor_row = self.mem_cursor / BYTES_PER_ROW;
+        if cursor_row < self.mem_scroll {
+            self.mem_scroll = cursor_row;
+        }
+        else if cursor_row >= self.mem_scroll + MEM_ROWS {
+            self.mem_scroll = cursor_row + 1 - MEM_ROWS;
+        }
+
+        for row in 0..MEM_ROWS {
+            let base = (self.mem_scroll + row) * BYTES_PER_ROW;
+            if base >= mem.len() {
+                break;
+            }
+            let y = TOP_ROW + row as i32;
+            self.label(HEX_COLUMN, y, &format!("{:#05X}:", base), palette.blend(0.5));
+
+            for offset in 0..BYTES_PER_ROW {
+                let addr = base + offset;
+                let col = HEX_COLUMN + 7 + offset as i32 * 3;
+                let selected = self.panel == Panel::Memory && addr == self.mem_cursor;
+                let value = self.field_text(Field::Mem(addr), emu, selected);
+
+                // PC covers both bytes of the instruction it points at
+                let color = if addr == pc || addr == pc + 1 {
+                    self.bar(col, y, 2, palette.fg);
+                    palette.bg
+                }
+                else if addr == i_reg {
+                    self.bar(col, y, 2, palette.blend(0.5));
+                    palette.fg
+                }
+                else {
+                    palette.fg
+                };
+                self.label(col, y, &value, color);
+
+                if selected {
+                    let (x, y) = cell(col, y);
+                    let cursor = Rect::new(x - SCALE as i32, y - SCALE as i32, 2 * text::ADVANCE * SCALE + SCALE, text::LINE_HEIGHT * SCALE);
+                    self.canvas.set_draw_color(palette.fg);
+                    self.canvas.draw_rect(cursor).unwrap();
+                }
+            }
+        }
+    }
+
+    // Frozen addresses, then the results of the current search. Only the results in view are
+    // looked up
+    fn draw_cheats(&mut self, emu: &Emu, cheats: &Cheats, palette: &Palette) {
+        let mem = emu.get_mem();
+        let frozen = cheats.list.get();
+        let listed = listed_results(cheats);
+
+        let heading = match &cheats.search {
+            _ if !self.edit.is_empty() => format!("Equal to {:_<2}?", self.edit),
+            Some(search) if search.count() > listed => format!("{} matches, narrow them down", search.count()),
+            Some(search) => format!("Results ({})", search.count()),
+            None => String::from("No search (N starts one)"),
+        };
+
+        // The lines are a heading, the frozen addresses, another heading and the results, so
+        // scroll just far enough to keep the cursor in view
+        let results_line = frozen.len() + 2;
+        let cursor_line = if self.cheat_cursor < frozen.len() { self.cheat_cursor + 1 } else { self.cheat_cursor + 2 };
+        if cursor_line < self.cheat_scroll {
+            self.cheat_scroll = cursor_line;
+        }
+        else if cursor_line >= self.cheat_scroll + CHEAT_ROWS {
+            self.cheat_scroll = cursor_line + 1 - CHEAT_ROWS;
+        }
+
+        let mut results = cheats.search.as_ref().map(|search| search.candidates(self.cheat_scroll.saturating_sub(results_line)));
+        let last_line = (results_line + listed).min(self.cheat_scroll + CHEAT_ROWS);
+        for line in self.cheat_scroll..last_line {
+            let row = TOP_ROW + (line - self.cheat_scroll) as i32;
+            if line == 0 || line == results_line - 1 {
+                let text = if line == 0 { format!("Frozen ({})", frozen.len()) } else { heading.clone() };
+                self.label(DISASM_COLUMN, row, &text, palette.blend(0.5));
+                continue;
+            }
+
+            let (item, text) = if line <= frozen.len() {
+                let cheat = frozen[line - 1];
+                (line - 1, format!("{:#05X} = {:02X}", cheat.addr, cheat.value))
+            }
+            else {
+                let addr = results.as_mut().and_then(|results| results.next()).unwrap();
+                let previous = cheats.search.as_ref().unwrap().previous(addr);
+                (line - 2, format!("{:#05X}  {:02X}  was {:02X}", addr, mem[addr], previous))
+            };
+
+            if item == self.cheat_cursor {
+                self.bar(DISASM_COLUMN, row, text.len(), palette.fg);
+                self.label(DISASM_COLUMN, row, &text, palette.bg);
+            }
+            else {
+                self.label(DISASM_COLUMN, row, &text, palette.fg);
+            }
+        }
+    }
+
+    // The value of a field as hex, or what's been typed so far if it's being edited
+    fn field_text(&self, field: Field, emu: &Emu, selected: bool) -> String {
+        let digits = field.digits(emu);
+        if selected && !self.edit.is_empty() {
+            format!("{:_<width$}", self.edit, width = digits)
+        }
+        else {
+            format!("{:0width$X}", field.read(emu), width = digits)
+        }
+    }
+
+    fn label(&mut self, col: i32, row: i32, text: &str, color: Color) {
+        let (x, y) = cell(col, row);
+        text::draw_text(&mut self.canvas, text, x, y, SCALE, color);
+    }
+
+    // Fill the cells behind some text to highlight it
+    fn bar(&mut self, col: i32, row: i32, len: usize, color: Color) {
+        let (x, y) = cell(col, row);
+        let width = len as u32 * text::ADVANCE * SCALE + SCALE;
+        self.canvas.set_draw_color(color);
+        self.canvas.fill_rect(Rect::new(x - SCALE as i32, y - SCALE as i32, width, text::LINE_HEIGHT * SCALE)).unwrap();
+    }
+}
+
+// Top left corner of a character cell in window pixels
+fn cell(col: i32, row: i32) -> (i32, i32) {
+    let x = col * (text::ADVANCE * SCALE) as i32;
+    let y = row * (text::LINE_HEIGHT * SCALE) as i32 + SCALE as i32;
+    (x, y)
+}
+
+fn hex_digit(key: Keycode) -> Option<char> {
+    let name = key.name();
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if c.is_ascii_hexdigit() => Some(c.to_ascii_uppercase()),
+        _ => None,
+    }
+}
+
+// How many search results the cheats panel lists, which is none while there are too many
+fn listed_results(cheats: &Cheats) -> usize {
+    match &cheats.search {
+        Some(search) if search.count() <= MAX_LISTED_RESULTS => search.count(),
+        _ => 0,
+    }
+}
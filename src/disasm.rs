@@ -0,0 +1,97 @@
+// Turn a single opcode into the mnemonic shown in traces and listings.
+// The output uses the same syntax the assembler accepts for the same platform.
+
+use crate::cpu::Platform;
+
+pub fn disassemble(op: u16, platform: Platform) -> String {
+    let nibble1 = (op & 0xF000) >> 12;
+    let nibble2 = (op & 0x0F00) >> 8;
+    let nibble3 = (op & 0x00F0) >> 4;
+    let nibble4 = op & 0x000F;
+
+    let x = nibble2;
+    let y = nibble3;
+    let nn = op & 0x00FF;
+    let nnn = op & 0x0FFF;
+
+    let chip8e = platform == Platform::Chip8E;
+    let chip8x = platform == Platform::Chip8X;
+    let megachip = platform == Platform::MegaChip;
+
+    match (nibble1, nibble2, nibble3, nibble4) {
+        (0, 2, 3, 0) if platform == Platform::Chip8Hires => String::from("HCLS"),
+        (0, 0, 0xE, 0xD) if chip8e => String::from("STOP"),
+        (0, 0, 0xF, 2) if chip8e => String::from("NOP"),
+        (0, 1, 5, 1) if chip8e => String::from("WAIT DT"),
+        (0, 1, 8, 8) if chip8e => String::from("SKIP"),
+        (0, 2, 0xA, 0) if chip8x => String::from("BGC"),
+        (0, 0, 1, 0) if megachip => String::from("MEGAOFF"),
+        (0, 0, 1, 1) if megachip => String::from("MEGAON"),
+        (0, 0, 0xB, _) if megachip => format!("SCRU {}", nibble4),
+        // The low 16 bits of the address are the next word
+        (0, 1, _, _) if megachip => format!("LDHI {:#04X}", nn),
+        (0, 2, _, _) if megachip => format!("LDPAL {}", nn),
+        (0, 3, _, _) if megachip => format!("SPRW {}", nn),
+        (0, 4, _, _) if megachip => format!("SPRH {}", nn),
+        (0, 5, _, _) if megachip => format!("ALPHA {:#04X}", nn),
+        (0, 6, 0, _) if megachip => format!("DIGISND {}", nibble4),
+        (0, 7, 0, 0) if megachip => String::from("STOPSND"),
+        (0, 8, 0, _) if megachip => format!("BMODE {}", nibble4),
+        (0, 9, _, _) if megachip => format!("CCOL {:#04X}", nn),
+        (5, _, _, 1) if chip8x => format!("NADD V{:X}, V{:X}", x, y),
+        (5, _, _, 1) if chip8e => format!("SGT V{:X}, V{:X}", x, y),
+        (5, _, _, 2) if chip8e => format!("STR V{:X}, V{:X}", x, y),
+        (5, _, _, 3) if chip8e => format!("LDR V{:X}, V{:X}", x, y),
+        (0xB, _, _, _) if chip8x => format!("COL V{:X}, V{:X}, {}", x, y, nibble4),
+        (0xB, 0xB, _, _) if chip8e => format!("JB {:#04X}", nn),
+        (0xB, 0xF, _, _) if chip8e => format!("JF {:#04X}", nn),
+        (0xE, _, 0xF, 2) if chip8x => format!("SKP2 V{:X}", x),
+        (0xE, _, 0xF, 5) if chip8x => format!("SKNP2 V{:X}", x),
+        (0xF, _, 0, 3) if chip8e => format!("OUT V{:X}", x),
+        (0xF, _, 1, 0xB) if chip8e => format!("SKIP V{:X}", x),
+        (0xF, _, 4, 0xF) if chip8e => format!("WAIT DT, V{:X}", x),
+        (0xF, _, 0xE, 3) if chip8e => format!("INS V{:X}", x),
+        (0xF, _, 0xE, 7) if chip8e => format!("IN V{:X}", x),
+        (0xF, _, 0xF, 8) if chip8x => format!("OUT V{:X}", x),
+        (0xF, _, 0xF, 0xB) if chip8x => format!("IN V{:X}", x),
+
+        (0, 0, 0xE, 0) => String::from("CLS"),
+        (0, 0, 0xE, 0xE) => String::from("RET"),
+        (0, _, _, _) => format!("SYS {:#05X}", nnn),
+        (1, _, _, _) => format!("JP {:#05X}", nnn),
+        (2, _, _, _) => format!("CALL {:#05X}", nnn),
+        (3, _, _, _) => format!("SE V{:X}, {:#04X}", x, nn),
+        (4, _, _, _) => format!("SNE V{:X}, {:#04X}", x, nn),
+        (5, _, _, 0) => format!("SE V{:X}, V{:X}", x, y),
+        (6, _, _, _) => format!("LD V{:X}, {:#04X}", x, nn),
+        (7, _, _, _) => format!("ADD V{:X}, {:#04X}", x, nn),
+        (8, _, _, 0) => format!("LD V{:X}, V{:X}", x, y),
+        (8, _, _, 1) => format!("OR V{:X}, V{:X}", x, y),
+        (8, _, _, 2) => format!("AND V{:X}, V{:X}", x, y),
+        (8, _, _, 3) => format!("XOR V{:X}, V{:X}", x, y),
+        (8, _, _, 4) => format!("ADD V{:X}, V{:X}", x, y),
+        (8, _, _, 5) => format!("SUB V{:X}, V{:X}", x, y),
+        (8, _, _, 6) => format!("SHR V{:X}, V{:X}", x, y),
+        (8, _, _, 7) => format!("SUBN V{:X}, V{:X}", x, y),
+        (8, _, _, 0xE) => format!("SHL V{:X}, V{:X}", x, y),
+        (9, _, _, 0) => format!("SNE V{:X}, V{:X}", x, y),
+        (0xA, _, _, _) => format!("LD I, {:#05X}", nnn),
+        (0xB, _, _, _) => format!("JP V0, {:#05X}", nnn),
+        (0xC, _, _, _) => format!("RND V{:X}, {:#04X}", x, nn),
+        (0xD, _, _, _) => format!("DRW V{:X}, V{:X}, {}", x, y, nibble4),
+        (0xE, _, 9, 0xE) => format!("SKP V{:X}", x),
+        (0xE, _, 0xA, 1) => format!("SKNP V{:X}", x),
+        (0xF, _, 0, 7) => format!("LD V{:X}, DT", x),
+        (0xF, _, 0, 0xA) => format!("LD V{:X}, K", x),
+        (0xF, _, 1, 5) => format!("LD DT, V{:X}", x),
+        (0xF, _, 1, 8) => format!("LD ST, V{:X}", x),
+        (0xF, _, 1, 0xE) => format!("ADD I, V{:X}", x),
+        (0xF, _, 2, 9) => format!("LD F, V{:X}", x),
+        (0xF, _, 3, 0) => format!("LD HF, V{:X}", x),
+        (0xF, _, 3, 3) => format!("LD B, V{:X}", x),
+        (0xF, _, 5, 5) => format!("LD [I], V{:X}", x),
+        (0xF, _, 6, 5) => format!("LD V{:X}, [I]", x),
+        // Not an instruction, most likely sprite or other data
+        (_, _, _, _) => format!("DW {:#06X}", op),
+    }
+}
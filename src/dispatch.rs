@@ -0,0 +1,112 @@
+// Predecoded instructions for running many machines quickly.
+//
+// Each address is decoded the first time PC reaches it, and the result is kept until
+// something writes over it. Only instructions that behave the same on every platform and
+// with every quirk are decoded into their own variant; the rest are left to Emu::execute.
+
+// PC is 16 bits, so nothing past here can be executed
+const MAX_ENTRIES: usize = 0x10000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Inst {
+    // 1NNN and 2NNN
+    Jump(u16),
+    Call(u16),
+    // 3XNN and 4XNN
+    SkipIfEqual { x: u8, nn: u8 },
+    SkipIfNotEqual { x: u8, nn: u8 },
+    // 5XY0 and 9XY0
+    SkipIfEqualReg { x: u8, y: u8 },
+    SkipIfNotEqualReg { x: u8, y: u8 },
+    // 6XNN and 7XNN
+    Set { x: u8, nn: u8 },
+    Add { x: u8, nn: u8 },
+    // 8XY0 to 8XY5 and 8XY7
+    Copy { x: u8, y: u8 },
+    Or { x: u8, y: u8 },
+    And { x: u8, y: u8 },
+    Xor { x: u8, y: u8 },
+    AddReg { x: u8, y: u8 },
+    Sub { x: u8, y: u8 },
+    SubReverse { x: u8, y: u8 },
+    // ANNN
+    SetIndex(u16),
+    // FX07, FX15 and FX18
+    GetDelay { x: u8 },
+    SetDelay { x: u8 },
+    SetSound { x: u8 },
+    // Anything else, run by Emu::execute
+    Other,
+}
+
+pub fn decode(op: u16) -> Inst {
+    let x = ((op & 0x0F00) >> 8) as u8;
+    let y = ((op & 0x00F0) >> 4) as u8;
+    let nn = (op & 0x00FF) as u8;
+    let nnn = op & 0x0FFF;
+
+    match (op >> 12, op & 0x000F) {
+        (1, _) => Inst::Jump(nnn),
+        (2, _) => Inst::Call(nnn),
+        (3, _) => Inst::SkipIfEqual { x, nn },
+        (4, _) => Inst::SkipIfNotEqual { x, nn },
+        (5, 0) => Inst::SkipIfEqualReg { x, y },
+        (6, _) => Inst::Set { x, nn },
+        (7, _) => Inst::Add { x, nn },
+        (8, 0) => Inst::Copy { x, y },
+        (8, 1) => Inst::Or { x, y },
+        (8, 2) => Inst::And { x, y },
+        (8, 3) => Inst::Xor { x, y },
+        (8, 4) => Inst::AddReg { x, y },
+        (8, 5) => Inst::Sub { x, y },
+        (8, 7) => Inst::SubReverse { x, y },
+        (9, 0) => Inst::SkipIfNotEqualReg { x, y },
+        (0xA, _) => Inst::SetIndex(nnn),
+        (0xF, _) => match nn {
+            0x07 => Inst::GetDelay { x },
+            0x15 => Inst::SetDelay { x },
+            0x18 => Inst::SetSound { x },
+            _ => Inst::Other,
+        },
+        _ => Inst::Other,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Decoded {
+    pub op: u16,
+    pub inst: Inst,
+}
+
+pub struct DispatchCache {
+    entries: Vec<Option<Decoded>>,
+}
+
+impl DispatchCache {
+    pub fn new(mem_len: usize) -> Self {
+        DispatchCache { entries: vec![None; mem_len.min(MAX_ENTRIES)] }
+    }
+
+    // The instruction at pc, decoding it if it hasn't been already. Reads past the end of
+    // mem panic the same way fetching does
+    #[inline]
+    pub fn get(&mut self, mem: &[u8], pc: u16) -> Decoded {
+        let addr = pc as usize;
+        if let Some(decoded) = self.entries[addr] {
+            return decoded;
+        }
+
+        let op = (mem[addr] as u16) << 8 | mem[addr + 1] as u16;
+        let decoded = Decoded { op, inst: decode(op) };
+        self.entries[addr] = Some(decoded);
+        decoded
+    }
+
+    // Forget anything decoded from len bytes at addr, including an instruction starting the
+    // byte before
+    pub fn invalidate(&mut self, addr: usize, len: usize) {
+        let start = addr.saturating_sub(1).min(self.entries.len());
+        let end = (addr + len).min(self.entries.len());
+        self.entries[start..end].fill(None);
+    }
+}
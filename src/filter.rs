@@ -0,0 +1,146 @@
+use std::collections::VecDeque;
+
+// How much of a pixel's brightness survives each frame once it turns off
+const PHOSPHOR_DECAY: f32 = 0.6;
+// Below this a fading pixel is treated as fully off
+const PHOSPHOR_CUTOFF: f32 = 0.05;
+const DEFAULT_BLEND_FRAMES: usize = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Filter {
+    None,
+    Phosphor,
+    Blend(usize),
+    Deflicker,
+}
+
+impl Filter {
+    pub fn from_name(name: &str) -> Option<Filter> {
+        match name {
+            "none" => Some(Filter::None),
+            "phosphor" => Some(Filter::Phosphor),
+            "blend" => Some(Filter::Blend(DEFAULT_BLEND_FRAMES)),
+            "deflicker" => Some(Filter::Deflicker),
+            _ => {
+                // Allow "blend:N" to pick how many frames get averaged
+                let frames = name.strip_prefix("blend:")?.parse::<usize>().ok()?;
+                if frames > 0 {
+                    Some(Filter::Blend(frames))
+                }
+                else {
+                    None
+                }
+            }
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Filter::None => String::from("none"),
+            Filter::Phosphor => String::from("phosphor"),
+            Filter::Blend(n) => format!("blend:{}", n),
+            Filter::Deflicker => String::from("deflicker"),
+        }
+    }
+
+    pub fn next(&self) -> Filter {
+        match self {
+            Filter::None => Filter::Phosphor,
+            Filter::Phosphor => Filter::Blend(DEFAULT_BLEND_FRAMES),
+            Filter::Blend(_) => Filter::Deflicker,
+            Filter::Deflicker => Filter::None,
+        }
+    }
+
+    // Whether the output keeps changing on frames where the display didn't
+    pub fn is_temporal(&self) -> bool {
+        *self != Filter::None
+    }
+
+    // Number of past frames the filter needs to look at
+    fn history_len(&self) -> usize {
+        match self {
+            Filter::None | Filter::Phosphor => 1,
+            Filter::Blend(n) => *n,
+            Filter::Deflicker => 2,
+        }
+    }
+}
+
+pub struct FrameHistory {
+    filter: Filter,
+    frames: VecDeque<Vec<bool>>,
+    intensity: Vec<f32>,
+}
+
+impl FrameHistory {
+    pub fn new(filter: Filter) -> Self {
+        Self {
+            filter,
+            frames: VecDeque::new(),
+            intensity: Vec::new(),
+        }
+    }
+
+    pub fn get_filter(&self) -> Filter {
+        self.filter
+    }
+
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+        self.frames.clear();
+        self.intensity.clear();
+    }
+
+    // Record the latest framebuffer and recompute the brightness of every pixel
+    pub fn push(&mut self, display: Vec<bool>) {
+        // Start over if the emulated resolution changed under us
+        if self.intensity.len() != display.len() {
+            self.frames.clear();
+            self.intensity = vec![0.0; display.len()];
+        }
+
+        self.frames.push_front(display);
+        self.frames.truncate(self.filter.history_len());
+        let display = &self.frames[0];
+
+        match self.filter {
+            Filter::None => {
+                for (level, pixel) in self.intensity.iter_mut().zip(display) {
+                    *level = if *pixel { 1.0 } else { 0.0 };
+                }
+            },
+            Filter::Phosphor => {
+                for (level, pixel) in self.intensity.iter_mut().zip(display) {
+                    if *pixel {
+                        *level = 1.0;
+                    }
+                    else {
+                        *level *= PHOSPHOR_DECAY;
+                        if *level < PHOSPHOR_CUTOFF {
+                            *level = 0.0;
+                        }
+                    }
+                }
+            },
+            Filter::Blend(_) => {
+                let count = self.frames.len() as f32;
+                for (i, level) in self.intensity.iter_mut().enumerate() {
+                    let lit = self.frames.iter().filter(|frame| frame[i]).count();
+                    *level = lit as f32 / count;
+                }
+            },
+            Filter::Deflicker => {
+                // A pixel is lit if it was on in either of the last two frames
+                for (i, level) in self.intensity.iter_mut().enumerate() {
+                    let lit = self.frames.iter().any(|frame| frame[i]);
+                    *level = if lit { 1.0 } else { 0.0 };
+                }
+            },
+        }
+    }
+
+    pub fn get_intensity(&self) -> &[f32] {
+        &self.intensity
+    }
+}
@@ -0,0 +1,75 @@
+// The monochrome screen, stored a bit per pixel.
+//
+// Each row is one or more u64 words with the leftmost pixel in the top bit, so a sprite
+// row can be XORed on and checked for collisions in one go. Frontends that want a bool per
+// pixel get one built from the rows when they ask.
+
+const WORD_BITS: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Framebuffer {
+    width: usize,
+    words_per_row: usize,
+    rows: Vec<u64>,
+}
+
+impl Framebuffer {
+    // width has to be a multiple of 64
+    pub fn new(width: usize, height: usize) -> Self {
+        let words_per_row = width / WORD_BITS;
+        Framebuffer {
+            width,
+            words_per_row,
+            rows: vec![0; words_per_row * height],
+        }
+    }
+
+    // Rows top to bottom, each words_per_row words long
+    pub fn rows(&self) -> &[u64] {
+        &self.rows
+    }
+
+    pub fn pixel_count(&self) -> usize {
+        self.rows.len() * WORD_BITS
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> bool {
+        self.rows[y * self.words_per_row + x / WORD_BITS] & (1 << (WORD_BITS - 1 - x % WORD_BITS)) != 0
+    }
+
+    // Every pixel, row by row
+    pub fn pixels(&self) -> impl Iterator<Item = bool> + '_ {
+        self.rows.iter().flat_map(|word| (0..WORD_BITS).map(move |col| word & (1 << (WORD_BITS - 1 - col)) != 0))
+    }
+
+    pub fn any(&self) -> bool {
+        self.rows.iter().any(|word| *word != 0)
+    }
+
+    pub fn clear(&mut self) {
+        self.rows.fill(0);
+    }
+
+    // Flip the pixels set in bits in word of row y. Returns whether any of them were on
+    pub fn xor_word(&mut self, y: usize, word: usize, bits: u64) -> bool {
+        let index = y * self.words_per_row + word;
+        let collision = self.rows[index] & bits != 0;
+        self.rows[index] ^= bits;
+        collision
+    }
+
+    pub fn set_word(&mut self, y: usize, word: usize, bits: u64) {
+        self.rows[y * self.words_per_row + word] = bits;
+    }
+
+    // Replace every pixel, row by row. Missing pixels are left off
+    pub fn load_pixels<I: IntoIterator<Item = bool>>(&mut self, pixels: I) {
+        self.clear();
+        for (index, pixel) in pixels.into_iter().take(self.pixel_count()).enumerate() {
+            if pixel {
+                let (y, x) = (index / self.width, index % self.width);
+                self.rows[y * self.words_per_row + x / WORD_BITS] |= 1 << (WORD_BITS - 1 - x % WORD_BITS);
+            }
+        }
+    }
+}
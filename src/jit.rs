@@ -0,0 +1,457 @@
+// Recompiles CHIP-8 into native code with Cranelift, for running lots of instructions fast.
+//
+// A block starts wherever PC lands and runs up to the first instruction that can go
+// somewhere other than the next one. Instructions that only touch the V registers and I are
+// compiled inline, as are jumps and skips. Everything else calls back into Emu::execute,
+// including DXYN and the timers, and anything there that can move PC, wait or write to
+// memory ends the block. Writing to memory throws away the blocks it lands in, and an
+// address that keeps being written over is left to the interpreter from then on. So is
+// 0NNN, since the machine code it runs could write anywhere.
+
+use std::any::Any;
+use std::collections::HashMap;
+
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{self, types, AbiParam, FuncRef, InstBuilder, MemFlags, Value};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncId, Linkage, Module};
+
+use crate::cpu::Emu;
+use crate::dispatch::{decode, Inst};
+
+// Most instructions in one block
+const MAX_BLOCK_LEN: usize = 64;
+// How many times the code at an address can be written over before it stops being compiled
+const MAX_REWRITES: u32 = 4;
+
+// Where the registers that compiled code works on directly sit inside Emu
+#[derive(Clone, Copy, Debug)]
+pub struct Layout {
+    pub v_reg: i32,
+    pub i_reg: i32,
+    pub pc: i32,
+}
+
+// Runs one instruction with Emu::execute, for blocks to call. Panics can't unwind through
+// compiled code, so it returns 0 instead after handing the panic to Jit::set_panic
+pub type Helper = extern "C" fn(*mut Emu, u32) -> u8;
+
+// Runs a whole block and returns the address to carry on from, or PANICKED
+type BlockFn = unsafe extern "C" fn(*mut Emu) -> u32;
+
+const PANICKED: u32 = u32::MAX;
+
+#[derive(Clone, Copy)]
+pub struct Block {
+    code: BlockFn,
+    // Where the bytes of memory the block was compiled from end
+    end: usize,
+    // Instructions in the block. A block always runs all of them
+    pub len: u32,
+}
+
+impl Block {
+    /// # Safety
+    ///
+    /// emu has to point to the Emu whose Jit compiled this block, with nothing else borrowing it
+    pub unsafe fn run(&self, emu: *mut Emu) -> Option<u16> {
+        match (self.code)(emu) {
+            PANICKED => None,
+            pc => Some(pc as u16),
+        }
+    }
+}
+
+// What a block does with an instruction
+enum Step {
+    Inline,
+    // Run by Emu::execute
+    Helper,
+    // Compiled, but nothing after it is
+    End,
+    // Run by Emu::execute, ending the block with wherever it left PC
+    Exit,
+    // Left out for the interpreter, ending the block before it
+    Stop,
+}
+
+#[derive(Clone, Copy)]
+enum Slot {
+    Unknown,
+    // Nothing could be compiled, or the code here keeps changing
+    Interpreted,
+    Compiled(Block),
+}
+
+pub struct Jit {
+    // None when Cranelift can't generate code for this machine, which leaves everything to
+    // the interpreter
+    module: Option<JITModule>,
+    helper: Option<FuncId>,
+    ctx: Context,
+    builder_ctx: FunctionBuilderContext,
+    layout: Layout,
+    // By start address
+    blocks: Vec<Slot>,
+    // Whether each byte of memory has ever been compiled, so writes to data can skip
+    // looking for blocks to throw away
+    compiled_from: Vec<bool>,
+    rewrites: HashMap<usize, u32>,
+    compiled: usize,
+    // Caught by the helper, for run_jit to carry on with once out of compiled code
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+impl Jit {
+    pub fn new(mem_len: usize, layout: Layout, helper: Helper) -> Self {
+        let mut module = host_module(helper);
+        let helper = module.as_mut().and_then(|module| {
+            let mut sig = module.make_signature();
+            sig.params.push(AbiParam::new(module.target_config().pointer_type()));
+            sig.params.push(AbiParam::new(types::I32));
+            sig.returns.push(AbiParam::new(types::I8));
+            module.declare_function("chip8_execute", Linkage::Import, &sig).ok()
+        });
+        let ctx = match module.as_ref() {
+            Some(module) => module.make_context(),
+            None => Context::new(),
+        };
+
+        Jit {
+            module,
+            helper,
+            ctx,
+            builder_ctx: FunctionBuilderContext::new(),
+            layout,
+            // PC is 16 bits, so nothing past here can be executed
+            blocks: vec![Slot::Unknown; mem_len.min(0x10000)],
+            compiled_from: vec![false; mem_len],
+            rewrites: HashMap::new(),
+            compiled: 0,
+            panic: None,
+        }
+    }
+
+    // The block starting at pc, compiling it if it hasn't been already. None leaves the
+    // instruction there to the interpreter, which also takes care of PC being out of range
+    #[inline]
+    pub fn get(&mut self, mem: &[u8], pc: u16) -> Option<Block> {
+        let addr = pc as usize;
+        match *self.blocks.get(addr)? {
+            Slot::Compiled(block) => return Some(block),
+            Slot::Interpreted => return None,
+            Slot::Unknown => (),
+        }
+
+        let rewritten = self.rewrites.get(&addr).is_some_and(|count| *count >= MAX_REWRITES);
+        let block = if rewritten { None } else { self.compile(mem, pc) };
+        self.blocks[addr] = match block {
+            Some(block) => Slot::Compiled(block),
+            None => Slot::Interpreted,
+        };
+        block
+    }
+
+    pub fn set_panic(&mut self, payload: Box<dyn Any + Send>) {
+        self.panic = Some(payload);
+    }
+
+    pub fn take_panic(&mut self) -> Option<Box<dyn Any + Send>> {
+        self.panic.take()
+    }
+
+    // Forget blocks compiled from any of len bytes at addr. The compiled code itself stays
+    // around until the Jit is dropped, so this is safe to call from inside a block
+    pub fn invalidate(&mut self, addr: usize, len: usize) {
+        let written = addr.min(self.compiled_from.len())..(addr + len).min(self.compiled_from.len());
+        if !self.compiled_from[written].contains(&true) {
+            return;
+        }
+
+        let first = addr.saturating_sub(MAX_BLOCK_LEN * 2 - 1).min(self.blocks.len());
+        let last = (addr + len).min(self.blocks.len());
+        for (start, slot) in self.blocks[first..last].iter_mut().enumerate() {
+            let start = first + start;
+            let end = match slot {
+                Slot::Compiled(block) => block.end,
+                Slot::Interpreted => start + 2,
+                Slot::Unknown => continue,
+            };
+            if end <= addr {
+                continue;
+            }
+            if let Slot::Compiled(_) = slot {
+                *self.rewrites.entry(start).or_insert(0) += 1;
+            }
+            *slot = Slot::Unknown;
+        }
+    }
+
+    fn compile(&mut self, mem: &[u8], pc: u16) -> Option<Block> {
+        let ops = block_ops(mem, pc as usize);
+        if ops.is_empty() {
+            return None;
+        }
+
+        let module = self.module.as_mut()?;
+        let helper = self.helper?;
+        let ptr_type = module.target_config().pointer_type();
+        self.ctx.func.signature.params.push(AbiParam::new(ptr_type));
+        self.ctx.func.signature.returns.push(AbiParam::new(types::I32));
+
+        let mut builder = FunctionBuilder::new(&mut self.ctx.func, &mut self.builder_ctx);
+        let helper = module.declare_func_in_func(helper, builder.func);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        builder.seal_block(entry);
+
+        let emu = builder.block_params(entry)[0];
+        let panicked = builder.create_block();
+        let mut emitter = Emitter { builder, emu, helper, panicked, layout: self.layout };
+        let mut addr = pc as u32;
+        let mut finished = false;
+        for op in &ops {
+            addr += 2;
+            finished = emitter.emit(*op, addr);
+        }
+        if !finished {
+            let next = emitter.builder.ins().iconst(types::I32, addr as i64);
+            emitter.builder.ins().return_(&[next]);
+        }
+        emitter.builder.switch_to_block(panicked);
+        let panicked = emitter.builder.ins().iconst(types::I32, PANICKED as i64);
+        emitter.builder.ins().return_(&[panicked]);
+        emitter.builder.seal_all_blocks();
+        emitter.builder.finalize();
+
+        let name = format!("block_{:04x}_{}", pc, self.compiled);
+        let defined = match module.declare_function(&name, Linkage::Local, &self.ctx.func.signature) {
+            Ok(id) => module.define_function(id, &mut self.ctx).ok().map(|_| id),
+            Err(_) => None,
+        };
+        module.clear_context(&mut self.ctx);
+        let id = defined?;
+        module.finalize_definitions().ok()?;
+        self.compiled += 1;
+        let end = pc as usize + ops.len() * 2;
+        self.compiled_from[pc as usize..end].fill(true);
+
+        let code = module.get_finalized_function(id);
+        Some(Block {
+            // Safety: the function was declared with exactly this signature above
+            code: unsafe { std::mem::transmute::<*const u8, BlockFn>(code) },
+            end,
+            len: ops.len() as u32,
+        })
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // Safety: blocks only live as long as the Jit that made them
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+fn host_module(helper: Helper) -> Option<JITModule> {
+    let mut flags = settings::builder();
+    flags.set("use_colocated_libcalls", "false").ok()?;
+    flags.set("is_pic", "false").ok()?;
+    flags.set("opt_level", "speed").ok()?;
+    let isa = cranelift_native::builder().ok()?.finish(settings::Flags::new(flags)).ok()?;
+
+    let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+    builder.symbol("chip8_execute", helper as *const u8);
+    Some(JITModule::new(builder))
+}
+
+// The instructions making up the block at addr, stopping short of the end of memory the
+// same way fetching would
+fn block_ops(mem: &[u8], mut addr: usize) -> Vec<u16> {
+    let mut ops = Vec::new();
+    while ops.len() < MAX_BLOCK_LEN && addr + 1 < mem.len() {
+        let op = u16::from_be_bytes([mem[addr], mem[addr + 1]]);
+        match step(op) {
+            Step::Inline | Step::Helper => ops.push(op),
+            Step::End | Step::Exit => {
+                ops.push(op);
+                break;
+            },
+            Step::Stop => break,
+        }
+        addr += 2;
+    }
+    ops
+}
+
+fn step(op: u16) -> Step {
+    match decode(op) {
+        Inst::Jump(_) | Inst::SkipIfEqual { .. } | Inst::SkipIfNotEqual { .. } | Inst::SkipIfEqualReg { .. }
+            | Inst::SkipIfNotEqualReg { .. } => Step::End,
+        Inst::Call(_) => Step::Exit,
+        Inst::GetDelay { .. } | Inst::SetDelay { .. } | Inst::SetSound { .. } => Step::Helper,
+        Inst::Other if stays_in_place(op) => Step::Helper,
+        Inst::Other if op >> 12 == 0 && op != 0x00EE => Step::Stop,
+        Inst::Other => Step::Exit,
+        _ => Step::Inline,
+    }
+}
+
+// Instructions without a variant of their own that can't move PC, wait, fault or write to
+// memory on any platform, so a block can carry on after them
+fn stays_in_place(op: u16) -> bool {
+    match (op >> 12, op & 0x00FF) {
+        // 00E0
+        (0, 0xE0) => op == 0x00E0,
+        // 8XY6 and 8XYE
+        (8, _) => matches!(op & 0x000F, 0x6 | 0xE),
+        // CXNN and DXYN
+        (0xC, _) | (0xD, _) => true,
+        // FX1E, FX29, FX30 and FX65
+        (0xF, 0x1E | 0x29 | 0x30 | 0x65) => true,
+        _ => false,
+    }
+}
+
+struct Emitter<'a> {
+    builder: FunctionBuilder<'a>,
+    emu: Value,
+    helper: FuncRef,
+    // Where to go when the helper panicked
+    panicked: ir::Block,
+    layout: Layout,
+}
+
+impl Emitter<'_> {
+    // Emit op, with next the address after it. Returns whether it ended the block
+    fn emit(&mut self, op: u16, next: u32) -> bool {
+        match decode(op) {
+            Inst::Jump(nnn) => {
+                let target = self.builder.ins().iconst(types::I32, nnn as i64);
+                self.builder.ins().return_(&[target]);
+                return true;
+            },
+            Inst::SkipIfEqual { x, nn } => {
+                let vx = self.load_v(x);
+                let skip = self.builder.ins().icmp_imm(IntCC::Equal, vx, nn as i8 as i64);
+                self.skip_if(skip, next);
+                return true;
+            },
+            Inst::SkipIfNotEqual { x, nn } => {
+                let vx = self.load_v(x);
+                let skip = self.builder.ins().icmp_imm(IntCC::NotEqual, vx, nn as i8 as i64);
+                self.skip_if(skip, next);
+                return true;
+            },
+            Inst::SkipIfEqualReg { x, y } => {
+                let (vx, vy) = (self.load_v(x), self.load_v(y));
+                let skip = self.builder.ins().icmp(IntCC::Equal, vx, vy);
+                self.skip_if(skip, next);
+                return true;
+            },
+            Inst::SkipIfNotEqualReg { x, y } => {
+                let (vx, vy) = (self.load_v(x), self.load_v(y));
+                let skip = self.builder.ins().icmp(IntCC::NotEqual, vx, vy);
+                self.skip_if(skip, next);
+                return true;
+            },
+            Inst::Set { x, nn } => {
+                let value = self.builder.ins().iconst(types::I8, nn as i8 as i64);
+                self.store_v(x, value);
+            },
+            Inst::Add { x, nn } => {
+                let vx = self.load_v(x);
+                let sum = self.builder.ins().iadd_imm(vx, nn as i8 as i64);
+                self.store_v(x, sum);
+            },
+            Inst::Copy { x, y } => {
+                let vy = self.load_v(y);
+                self.store_v(x, vy);
+            },
+            Inst::Or { x, y } => {
+                let (vx, vy) = (self.load_v(x), self.load_v(y));
+                let value = self.builder.ins().bor(vx, vy);
+                self.store_v(x, value);
+            },
+            Inst::And { x, y } => {
+                let (vx, vy) = (self.load_v(x), self.load_v(y));
+                let value = self.builder.ins().band(vx, vy);
+                self.store_v(x, value);
+            },
+            Inst::Xor { x, y } => {
+                let (vx, vy) = (self.load_v(x), self.load_v(y));
+                let value = self.builder.ins().bxor(vx, vy);
+                self.store_v(x, value);
+            },
+            // VF is written before VX, the same as the interpreter, so VX wins when X is F
+            Inst::AddReg { x, y } => {
+                let (vx, vy) = (self.load_v(x), self.load_v(y));
+                let sum = self.builder.ins().iadd(vx, vy);
+                let carry = self.builder.ins().icmp(IntCC::UnsignedLessThan, sum, vx);
+                self.store_v(0xF, carry);
+                self.store_v(x, sum);
+            },
+            Inst::Sub { x, y } => {
+                let (vx, vy) = (self.load_v(x), self.load_v(y));
+                let diff = self.builder.ins().isub(vx, vy);
+                let no_borrow = self.builder.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, vx, vy);
+                self.store_v(0xF, no_borrow);
+                self.store_v(x, diff);
+            },
+            Inst::SubReverse { x, y } => {
+                let (vx, vy) = (self.load_v(x), self.load_v(y));
+                let diff = self.builder.ins().isub(vy, vx);
+                let no_borrow = self.builder.ins().icmp(IntCC::UnsignedGreaterThanOrEqual, vy, vx);
+                self.store_v(0xF, no_borrow);
+                self.store_v(x, diff);
+            },
+            Inst::SetIndex(nnn) => {
+                let value = self.builder.ins().iconst(types::I32, nnn as i64);
+                self.builder.ins().store(MemFlags::trusted(), value, self.emu, self.layout.i_reg);
+            },
+            Inst::GetDelay { .. } | Inst::SetDelay { .. } | Inst::SetSound { .. } => self.call_execute(op, next),
+            Inst::Other if stays_in_place(op) => self.call_execute(op, next),
+            Inst::Call(_) | Inst::Other => {
+                self.call_execute(op, next);
+                let pc = self.builder.ins().uload16(types::I32, MemFlags::trusted(), self.emu, self.layout.pc);
+                self.builder.ins().return_(&[pc]);
+                return true;
+            },
+        }
+        false
+    }
+
+    // Emu::execute expects PC to already be past the instruction, the same as after fetching
+    fn call_execute(&mut self, op: u16, next: u32) {
+        let pc = self.builder.ins().iconst(types::I16, next as i64);
+        self.builder.ins().store(MemFlags::trusted(), pc, self.emu, self.layout.pc);
+        let op = self.builder.ins().iconst(types::I32, op as i64);
+        let call = self.builder.ins().call(self.helper, &[self.emu, op]);
+        let ok = self.builder.inst_results(call)[0];
+
+        let carry_on = self.builder.create_block();
+        self.builder.ins().brif(ok, carry_on, &[], self.panicked, &[]);
+        self.builder.switch_to_block(carry_on);
+    }
+
+    fn load_v(&mut self, x: u8) -> Value {
+        self.builder.ins().load(types::I8, MemFlags::trusted(), self.emu, self.layout.v_reg + x as i32)
+    }
+
+    fn store_v(&mut self, x: u8, value: Value) {
+        self.builder.ins().store(MemFlags::trusted(), value, self.emu, self.layout.v_reg + x as i32);
+    }
+
+    fn skip_if(&mut self, skip: Value, next: u32) {
+        let skipped = self.builder.ins().iconst(types::I32, next as i64 + 2);
+        let not_skipped = self.builder.ins().iconst(types::I32, next as i64);
+        let target = self.builder.ins().select(skip, skipped, not_skipped);
+        self.builder.ins().return_(&[target]);
+    }
+}
@@ -0,0 +1,103 @@
+// Which keyboard key presses which key on the hex keypad, shared by the frontends. Keys are
+// named the way SDL names them, and each frontend turns names into its own key type
+
+use std::collections::HashMap;
+use std::fs;
+use std::hash::Hash;
+use std::path::Path;
+
+use crate::cpu::Platform;
+
+const LAYOUT: [(&str, usize); 16] = [
+    ("1", 0x1), ("2", 0x2), ("3", 0x3), ("4", 0xC),
+    ("Q", 0x4), ("W", 0x5), ("E", 0x6), ("R", 0xD),
+    ("A", 0x7), ("S", 0x8), ("D", 0x9), ("F", 0xE),
+    ("Z", 0xA), ("X", 0x0), ("C", 0xB), ("V", 0xF),
+];
+
+// CHIP-8X's second keypad goes on the numeric keypad, laid out the same way
+const LAYOUT2: [(&str, usize); 16] = [
+    ("Keypad 7", 0x1), ("Keypad 8", 0x2), ("Keypad 9", 0x3), ("Keypad /", 0xC),
+    ("Keypad 4", 0x4), ("Keypad 5", 0x5), ("Keypad 6", 0x6), ("Keypad *", 0xD),
+    ("Keypad 1", 0x7), ("Keypad 2", 0x8), ("Keypad 3", 0x9), ("Keypad -", 0xE),
+    ("Keypad 0", 0xA), ("Keypad .", 0x0), ("Keypad Enter", 0xB), ("Keypad +", 0xF),
+];
+
+// The DREAM 6800's keypad counts up from the bottom left, so its keys go where they'd be
+// on the real thing
+const DREAM6800_LAYOUT: [(&str, usize); 16] = [
+    ("1", 0xC), ("2", 0xD), ("3", 0xE), ("4", 0xF),
+    ("Q", 0x8), ("W", 0x9), ("E", 0xA), ("R", 0xB),
+    ("A", 0x4), ("S", 0x5), ("D", 0x6), ("F", 0x7),
+    ("Z", 0x0), ("X", 0x1), ("C", 0x2), ("V", 0x3),
+];
+
+// The keys on the hex keypad and on CHIP-8X's second one
+#[derive(Clone)]
+pub struct Keymap<K> {
+    keys: HashMap<K, usize>,
+    keys2: HashMap<K, usize>,
+}
+
+impl<K: Copy + Eq + Hash> Keymap<K> {
+    // The layout that suits the platform's own keypad. Keys the frontend doesn't have are left out
+    pub fn for_platform(platform: Platform, from_name: impl Fn(&str) -> Option<K>) -> Self {
+        let (layout, layout2) = match platform {
+            Platform::Dream6800 => (&DREAM6800_LAYOUT, &[][..]),
+            Platform::Chip8 | Platform::Chip8X | Platform::Chip8Hires | Platform::MegaChip | Platform::Eti660
+                | Platform::Chip8E => (&LAYOUT, &LAYOUT2[..]),
+        };
+        let keys = |layout: &[(&str, usize)]| {
+            layout.iter().filter_map(|(name, index)| Some((from_name(name)?, *index))).collect()
+        };
+
+        Keymap {
+            keys: keys(layout),
+            keys2: keys(layout2),
+        }
+    }
+
+    // Read lines like `Q = 4`, where the left side is an SDL key name, or `Keypad 7 = 2:1`
+    // for the second keypad. Blank lines and anything after a # are ignored. The frontend's
+    // reserved keys can't be used
+    pub fn load(path: &Path, from_name: impl Fn(&str) -> Option<K>, reserved: &[K]) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("couldn't read keymap {}: {}", path.display(), e))?;
+        let mut keys = HashMap::new();
+        let mut keys2 = HashMap::new();
+
+        for (i, raw) in text.lines().enumerate() {
+            let line = raw.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let bad_line = || format!("{} line {}: expected `key = hex digit` or `key = 2:hex digit`", path.display(), i + 1);
+            let (name, digit) = line.split_once('=').ok_or_else(bad_line)?;
+            let key = from_name(name.trim())
+                .ok_or(format!("{} line {}: unknown key '{}'", path.display(), i + 1, name.trim()))?;
+            if reserved.contains(&key) {
+                return Err(format!("{} line {}: '{}' is one of the emulator's own keys", path.display(), i + 1, name.trim()));
+            }
+            let (pad, digit) = match digit.trim().strip_prefix("2:") {
+                Some(digit) => (&mut keys2, digit),
+                None => (&mut keys, digit.trim()),
+            };
+            let index = usize::from_str_radix(digit, 16).ok()
+                .filter(|index| *index < 16)
+                .ok_or_else(bad_line)?;
+
+            pad.insert(key, index);
+        }
+
+        Ok(Keymap { keys, keys2 })
+    }
+
+    pub fn get(&self, key: K) -> Option<usize> {
+        self.keys.get(&key).copied()
+    }
+
+    pub fn get2(&self, key: K) -> Option<usize> {
+        self.keys2.get(&key).copied()
+    }
+}
@@ -0,0 +1,21 @@
+#[cfg(feature = "cli")]
+pub mod args;
+pub mod asm;
+pub mod cdp1802;
+pub mod cheat;
+pub mod cpu;
+pub mod disasm;
+pub mod dispatch;
+pub mod font;
+pub mod framebuffer;
+pub mod keymap;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod megachip;
+pub mod rom;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+#[cfg(feature = "libretro")]
+pub mod libretro;
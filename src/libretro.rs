@@ -0,0 +1,502 @@
+// libretro core, built with `cargo build --release --no-default-features --features libretro --lib`
+// and loaded by RetroArch as the resulting libyachip8emu shared library.
+
+use std::collections::BTreeMap;
+use std::ffi::{c_char, c_uint, c_void, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+
+use crate::cheat::CheatList;
+use crate::cpu::Emu;
+use crate::cpu::Platform;
+use crate::megachip::SoundEvent;
+
+const RETRO_API_VERSION: c_uint = 1;
+const RETRO_DEVICE_JOYPAD: c_uint = 1;
+const RETRO_REGION_NTSC: c_uint = 0;
+const RETRO_ENVIRONMENT_GET_CAN_DUPE: c_uint = 3;
+const RETRO_ENVIRONMENT_SET_PIXEL_FORMAT: c_uint = 10;
+const RETRO_PIXEL_FORMAT_XRGB8888: c_uint = 1;
+
+const TICKS_PER_FRAME: usize = 10;
+const FPS: f64 = 60.0;
+const SAMPLE_RATE: f64 = 44100.0;
+const SAMPLES_PER_FRAME: usize = (SAMPLE_RATE / FPS) as usize;
+const TONE_HZ: f64 = 440.0;
+const VOLUME: i16 = 4000;
+const BG_COLOR: u32 = 0x0054_31A3;
+const FG_COLOR: u32 = 0x007B_9CED;
+
+// Joypad button ids in the order libretro numbers them, mapped onto the hex keypad
+// so the d-pad lines up with WASD (5, 7, 8, 9) on the keyboard frontends
+const JOYPAD_MAP: [usize; 16] = [
+    0x4, // B
+    0x2, // Y
+    0x0, // Select
+    0xF, // Start
+    0x5, // Up
+    0x8, // Down
+    0x7, // Left
+    0x9, // Right
+    0x6, // A
+    0x1, // X
+    0x3, // L
+    0xC, // R
+    0xD, // L2
+    0xE, // R2
+    0xA, // L3
+    0xB, // R3
+];
+
+type EnvironmentFn = extern "C" fn(cmd: c_uint, data: *mut c_void) -> bool;
+type VideoRefreshFn = extern "C" fn(data: *const c_void, width: c_uint, height: c_uint, pitch: usize);
+type AudioSampleFn = extern "C" fn(left: i16, right: i16);
+type AudioSampleBatchFn = extern "C" fn(data: *const i16, frames: usize) -> usize;
+type InputPollFn = extern "C" fn();
+type InputStateFn = extern "C" fn(port: c_uint, device: c_uint, index: c_uint, id: c_uint) -> i16;
+
+#[repr(C)]
+pub struct RetroSystemInfo {
+    library_name: *const c_char,
+    library_version: *const c_char,
+    valid_extensions: *const c_char,
+    need_fullpath: bool,
+    block_extract: bool,
+}
+
+#[repr(C)]
+pub struct RetroGameGeometry {
+    base_width: c_uint,
+    base_height: c_uint,
+    max_width: c_uint,
+    max_height: c_uint,
+    aspect_ratio: f32,
+}
+
+#[repr(C)]
+pub struct RetroSystemTiming {
+    fps: f64,
+    sample_rate: f64,
+}
+
+#[repr(C)]
+pub struct RetroSystemAvInfo {
+    geometry: RetroGameGeometry,
+    timing: RetroSystemTiming,
+}
+
+#[repr(C)]
+pub struct RetroGameInfo {
+    path: *const c_char,
+    data: *const c_void,
+    size: usize,
+    meta: *const c_char,
+}
+
+#[derive(Clone, Copy)]
+struct Callbacks {
+    environment: Option<EnvironmentFn>,
+    video_refresh: Option<VideoRefreshFn>,
+    audio_sample_batch: Option<AudioSampleBatchFn>,
+    input_poll: Option<InputPollFn>,
+    input_state: Option<InputStateFn>,
+}
+
+struct Core {
+    emu: Emu,
+    rom: Vec<u8>,
+    frame: Vec<u32>,
+    audio: Vec<i16>,
+    // Position within the current square wave period, from 0 to 1
+    phase: f64,
+    // MegaChip's sampled sound, empty when nothing is playing
+    samples: Vec<u8>,
+    sample_pos: f64,
+    sample_step: f64,
+    looping: bool,
+    // Enabled cheats by the frontend's index for them
+    cheats: BTreeMap<c_uint, CheatList>,
+    // Whether the frontend can reuse the last frame, so an unchanged screen needn't be sent
+    can_dupe: bool,
+    // Set when the emulator panicked, which stops it until the game is reset rather than
+    // unwinding into the frontend
+    crashed: bool,
+}
+
+impl Core {
+    fn new(rom: Vec<u8>, can_dupe: bool) -> Option<Self> {
+        let mut core = Core {
+            emu: Emu::new(),
+            rom,
+            frame: Vec::new(),
+            audio: vec![0; SAMPLES_PER_FRAME * 2],
+            phase: 0.0,
+            samples: Vec::new(),
+            sample_pos: 0.0,
+            sample_step: 0.0,
+            looping: false,
+            cheats: BTreeMap::new(),
+            can_dupe,
+            crashed: false,
+        };
+        if core.boot() {
+            Some(core)
+        }
+        else {
+            None
+        }
+    }
+
+    // Returns false if the ROM doesn't fit in memory
+    fn boot(&mut self) -> bool {
+        self.crashed = false;
+        self.emu.set_platform(Platform::detect(&self.rom));
+        self.emu.reset();
+        self.emu.load(&self.rom).is_ok()
+    }
+
+    fn render(&mut self) {
+        self.frame.clear();
+        // A MegaChip frame is already in colour
+        if let Some(frame) = self.emu.get_frame() {
+            self.frame.extend_from_slice(frame);
+            return;
+        }
+
+        // Rows follow each other word by word, so the pixels come out in order
+        for word in self.emu.get_packed_display() {
+            self.frame.extend((0..64).map(|col| if word << col >> 63 != 0 { FG_COLOR } else { BG_COLOR }));
+        }
+    }
+
+    fn mix_audio(&mut self) {
+        match self.emu.take_sound() {
+            Some(SoundEvent::Play { samples, rate, looping }) => {
+                self.samples = samples;
+                self.sample_pos = 0.0;
+                self.sample_step = rate as f64 / SAMPLE_RATE;
+                self.looping = looping;
+            },
+            Some(SoundEvent::Stop) => self.samples.clear(),
+            None => (),
+        }
+
+        let (_, s_timer) = self.emu.get_timers();
+        let step = TONE_HZ / SAMPLE_RATE;
+
+        for frame in self.audio.chunks_mut(2) {
+            let mut sample = if s_timer > 0 {
+                if self.phase < 0.5 { VOLUME } else { -VOLUME }
+            }
+            else {
+                0
+            };
+            self.phase = (self.phase + step) % 1.0;
+
+            if !self.samples.is_empty() {
+                sample += (self.samples[self.sample_pos as usize] as i16 - 128) * (VOLUME / 128);
+                self.sample_pos += self.sample_step;
+                if self.sample_pos as usize >= self.samples.len() {
+                    if self.looping {
+                        self.sample_pos %= self.samples.len() as f64;
+                    }
+                    else {
+                        self.samples.clear();
+                    }
+                }
+            }
+
+            frame[0] = sample;
+            frame[1] = sample;
+        }
+    }
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(cb: EnvironmentFn) {
+    CALLBACKS.lock().unwrap().environment = Some(cb);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(cb: VideoRefreshFn) {
+    CALLBACKS.lock().unwrap().video_refresh = Some(cb);
+}
+
+// We always send a whole frame of audio at once through the batch callback
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_cb: AudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(cb: AudioSampleBatchFn) {
+    CALLBACKS.lock().unwrap().audio_sample_batch = Some(cb);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(cb: InputPollFn) {
+    CALLBACKS.lock().unwrap().input_poll = Some(cb);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(cb: InputStateFn) {
+    CALLBACKS.lock().unwrap().input_state = Some(cb);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *CORE.lock().unwrap() = None;
+}
+
+/// # Safety
+/// `info` must point to a writable `retro_system_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    *info = RetroSystemInfo {
+        library_name: c"yachip8emu".as_ptr(),
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char,
+        valid_extensions: c"ch8|sc8|xo8|mc8".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+/// `info` must point to a writable `retro_system_av_info`.
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    let ((width, height), (max_width, max_height)) = match CORE.lock().unwrap().as_ref() {
+        Some(core) => (core.emu.get_screen_size(), core.emu.get_platform().max_screen_size()),
+        None => (Emu::new().get_screen_size(), Platform::Chip8.max_screen_size()),
+    };
+
+    *info = RetroSystemAvInfo {
+        geometry: RetroGameGeometry {
+            base_width: width as c_uint,
+            base_height: height as c_uint,
+            max_width: max_width as c_uint,
+            max_height: max_height as c_uint,
+            aspect_ratio: width as f32 / height as f32,
+        },
+        timing: RetroSystemTiming {
+            fps: FPS,
+            sample_rate: SAMPLE_RATE,
+        },
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        core.boot();
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    // Copied out so a frontend that sets callbacks from inside one doesn't deadlock
+    let callbacks = *CALLBACKS.lock().unwrap();
+    let mut guard = CORE.lock().unwrap();
+    let core = match guard.as_mut() {
+        Some(core) => core,
+        None => return,
+    };
+
+    if let Some(input_poll) = callbacks.input_poll {
+        input_poll();
+    }
+    if let Some(input_state) = callbacks.input_state {
+        for (id, key) in JOYPAD_MAP.iter().enumerate() {
+            let pressed = input_state(0, RETRO_DEVICE_JOYPAD, 0, id as c_uint) != 0;
+            core.emu.keypress(*key, pressed);
+        }
+    }
+
+    if !core.crashed {
+        let emu = &mut core.emu;
+        let ran = panic::catch_unwind(AssertUnwindSafe(|| {
+            for cheat in core.cheats.values() {
+                cheat.apply(emu);
+            }
+            for _ in 0..TICKS_PER_FRAME {
+                emu.tick();
+            }
+        }));
+        core.crashed = ran.is_err();
+    }
+    core.mix_audio();
+    if !core.crashed {
+        core.emu.tick_timers();
+    }
+
+    if let Some(video_refresh) = callbacks.video_refresh {
+        let (width, height) = core.emu.get_screen_size();
+        let dirty = core.emu.take_dirty().is_some();
+        if dirty || core.frame.is_empty() {
+            core.render();
+        }
+        // Passing a null frame tells the frontend to reuse the last one, for those that can
+        let data = if dirty || !core.can_dupe {
+            core.frame.as_ptr() as *const c_void
+        }
+        else {
+            ptr::null()
+        };
+        video_refresh(data, width as c_uint, height as c_uint, width * 4);
+    }
+
+    if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+        audio_sample_batch(core.audio.as_ptr(), SAMPLES_PER_FRAME);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    match CORE.lock().unwrap().as_ref() {
+        Some(core) => core.emu.state_size(),
+        None => Emu::new().state_size(),
+    }
+}
+
+/// # Safety
+/// `data` must point to at least `size` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let guard = CORE.lock().unwrap();
+    let core = match guard.as_ref() {
+        Some(core) => core,
+        None => return false,
+    };
+
+    let state = core.emu.save_state();
+    if size < state.len() {
+        return false;
+    }
+    ptr::copy_nonoverlapping(state.as_ptr(), data as *mut u8, state.len());
+
+    true
+}
+
+/// # Safety
+/// `data` must point to at least `size` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let mut guard = CORE.lock().unwrap();
+    match guard.as_mut() {
+        Some(core) => {
+            let state = slice::from_raw_parts(data as *const u8, size);
+            let loaded = core.emu.load_state(state).is_ok();
+            // The state is from before whatever crashed
+            if loaded {
+                core.crashed = false;
+            }
+            loaded
+        },
+        None => false,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {
+    if let Some(core) = CORE.lock().unwrap().as_mut() {
+        core.cheats.clear();
+    }
+}
+
+/// # Safety
+/// `code` must be null or point to a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn retro_cheat_set(index: c_uint, enabled: bool, code: *const c_char) {
+    let mut guard = CORE.lock().unwrap();
+    let core = match guard.as_mut() {
+        Some(core) => core,
+        None => return,
+    };
+
+    core.cheats.remove(&index);
+    if !enabled || code.is_null() {
+        return;
+    }
+
+    // Codes look like `2F0=09`, with several joined by `+`
+    let code = CStr::from_ptr(code).to_string_lossy().replace('+', "\n");
+    // Codes we can't make sense of are just left off
+    if let Ok(cheat) = CheatList::parse(&code) {
+        core.cheats.insert(index, cheat);
+    }
+}
+
+/// # Safety
+/// `game` must be null or point to a valid `retro_game_info` whose data holds `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    if game.is_null() || (*game).data.is_null() {
+        return false;
+    }
+
+    let mut can_dupe = false;
+    let environment = CALLBACKS.lock().unwrap().environment;
+    if let Some(environment) = environment {
+        let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+        if !environment(RETRO_ENVIRONMENT_SET_PIXEL_FORMAT, &mut format as *mut c_uint as *mut c_void) {
+            return false;
+        }
+        // Frontends that don't answer can't be assumed to handle a null frame
+        if !environment(RETRO_ENVIRONMENT_GET_CAN_DUPE, &mut can_dupe as *mut bool as *mut c_void) {
+            can_dupe = false;
+        }
+    }
+
+    let rom = slice::from_raw_parts((*game).data as *const u8, (*game).size).to_vec();
+    let core = Core::new(rom, can_dupe);
+    let loaded = core.is_some();
+    *CORE.lock().unwrap() = core;
+
+    loaded
+}
+
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(_game_type: c_uint, _info: *const RetroGameInfo, _num_info: usize) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *CORE.lock().unwrap() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(_id: c_uint) -> *mut c_void {
+    ptr::null_mut()
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(_id: c_uint) -> usize {
+    0
+}
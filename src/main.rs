@@ -1,142 +1,699 @@
-mod cpu;
+mod audio;
+mod cheats;
+mod cli;
+mod debugger;
+mod filter;
+mod menu;
+mod overlay;
+mod romdb;
+mod text;
+mod video;
 
-use std::env;
-use std::fs::File;
-use std::io::Read;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
 
-use sdl2::pixels::Color;
+use clap::Parser;
+
+use sdl2::pixels::PixelFormatEnum;
 use sdl2::event::Event;
+use sdl2::event::WindowEvent;
+use sdl2::controller::Button;
+use sdl2::controller::GameController;
 use sdl2::render::Canvas;
+use sdl2::render::Texture;
+use sdl2::video::FullscreenType;
 use sdl2::video::Window;
 use sdl2::keyboard::Keycode;
+use sdl2::keyboard::Mod;
+use sdl2::pixels::Color;
+use sdl2::rect::Point;
 use sdl2::rect::Rect;
 
-use cpu::Emu;
-use cpu::SCREEN_WIDTH;
-use cpu::SCREEN_HEIGHT;
+use yachip8emu::args::build_emu;
+use yachip8emu::args::MachineArgs;
+use yachip8emu::args::PlatformArgs;
+use yachip8emu::args::PlatformName;
+use yachip8emu::asm;
+use yachip8emu::cpu::DirtyRect;
+use yachip8emu::cpu::Emu;
+use yachip8emu::cpu::Platform;
+use yachip8emu::cpu::ZONE_WIDTH;
+use yachip8emu::disasm;
+use yachip8emu::keymap::Keymap;
+use yachip8emu::megachip::SoundEvent;
+use yachip8emu::rom::RomInfo;
 
-const SCALE: u32 = 15;
-const WINDOW_WIDTH: u32 = (SCREEN_WIDTH as u32) * SCALE;
-const WINDOW_HEIGHT: u32 = (SCREEN_HEIGHT as u32) * SCALE;
-const TICKS_PER_FRAME: usize = 10;
+use cheats::Cheats;
 
-fn main() {
-    let args: Vec<_> = env::args().collect();
+use cli::Cli;
+use cli::Command;
+use cli::RunArgs;
+use cli::TestArgs;
 
-    let mut cosmac_on = false;
+use debugger::Debugger;
 
-    if args.len() != 2 {
-        if args.len() == 3 && &args[2] == "-c" {
-            println!("COSMAC toggled on");
-            cosmac_on = true;
-        } 
-        else {
-            println!("Usage: cargo run path/to/game");
-            println!("Options:");
-            println!("   -c: toggle original COSMAC VIP functionality");
-            return;
+use filter::FrameHistory;
+
+use menu::Menu;
+use menu::MenuItem;
+
+use overlay::Overlay;
+
+use romdb::RomDb;
+
+use video::Palette;
+use video::ScaleMode;
+use video::vp590_color;
+
+const FPS: f64 = 60.0;
+// Keys the window handles itself, which a keymap file can't take over
+const RESERVED_KEYS: [Keycode; 8] = [
+    Keycode::P, Keycode::Tab, Keycode::Escape, Keycode::F5, Keycode::F9, Keycode::F10, Keycode::F11, Keycode::F12,
+];
+// The VP-595 sound board divides this clock by the CHIP-8X output port value plus one
+const VP595_CLOCK_HZ: f32 = 27_535.0;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Run(args) => run(args),
+        Command::Disasm { rom, platform } => disassemble(&rom, &platform),
+        Command::Asm { source, output, platform } => assemble(&source, &output, &platform),
+        Command::Info { rom, platform } => info(&rom, &platform),
+        Command::Test(args) => test(args),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            ExitCode::FAILURE
         }
     }
-    else {
-        println!("COSMAC toggled off");
-    }
+}
+
+fn read_rom(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|e| format!("couldn't read {}: {}", path.display(), e))
+}
+
+fn run(args: RunArgs) -> Result<(), String> {
+    // Without a ROM we start in the browser with nothing loaded yet
+    let mut rom = match &args.rom {
+        Some(path) => read_rom(path)?,
+        None => Vec::new(),
+    };
+    let mut chip8_inst = build_emu(&args.machine, &rom)?;
+
+    let mut menu = match &args.rom_dir {
+        Some(dir) => {
+            let db = match &args.rom_db {
+                Some(path) => RomDb::load(path)?,
+                None => RomDb::default(),
+            };
+            Some(Menu::scan(dir, &db)?)
+        },
+        None => None,
+    };
+    let mut in_menu = args.rom.is_none();
+    let mut cheats = Cheats::load(args.cheat_dir.as_deref(), &rom)?;
 
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsys = sdl_context.video().unwrap();
-    let window = video_subsys
-        .window("yachip8emu", WINDOW_WIDTH, WINDOW_HEIGHT)
+    let user_keymap = match &args.keymap {
+        Some(path) => Some(Keymap::load(path, Keycode::from_name, &RESERVED_KEYS)?),
+        None => None,
+    };
+    let mut keymap = pick_keymap(&user_keymap, chip8_inst.get_platform());
+    let palette = args.palette.unwrap_or_default();
+    let scale_mode = args.scale_mode;
+    let mut grid = args.grid;
+    let mut paused = args.paused;
+
+    // Keep pixels sharp when the GPU scales the framebuffer texture
+    sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "0");
+
+    let sdl_context = sdl2::init()?;
+    let video_subsys = sdl_context.video()?;
+
+    let (screen_w, screen_h) = chip8_inst.get_screen_size();
+
+    let mut window = video_subsys
+        .window("yachip8emu", (screen_w as u32) * args.scale, (screen_h as u32) * args.scale)
         .position_centered()
+        .resizable()
         .opengl()
         .build()
-        .unwrap();
-    
+        .map_err(|e| e.to_string())?;
+    window.set_minimum_size(screen_w as u32, screen_h as u32).map_err(|e| e.to_string())?;
+
     let mut canvas = window
         .into_canvas()
         .present_vsync()
         .build()
-        .unwrap();
+        .map_err(|e| e.to_string())?;
     canvas.clear();
     canvas.present();
+    set_title(&mut canvas, paused);
 
-    let mut event_pump = sdl_context.event_pump().unwrap();
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator
+        .create_texture_streaming(PixelFormatEnum::RGB24, screen_w as u32, screen_h as u32)
+        .map_err(|e| e.to_string())?;
+
+    // Carry on silently if there's no sound device rather than refusing to start
+    let mut beeper = if args.mute {
+        None
+    }
+    else {
+        match sdl_context.audio().and_then(|audio_subsys| audio::open_beeper(&audio_subsys)) {
+            Ok(device) => Some(device),
+            Err(e) => {
+                eprintln!("warning: no sound: {}", e);
+                None
+            }
+        }
+    };
+    let mut beeping = false;
+    let mut tone_port = None;
 
-    let mut chip8_inst = Emu::new();
+    // Gamepads are only used for the ROM browser, so do without them if they won't start
+    let controller_subsys = sdl_context.game_controller()
+        .map_err(|e| eprintln!("warning: no gamepad support: {}", e))
+        .ok();
+    let mut controllers: Vec<GameController> = Vec::new();
 
-    if cosmac_on {
-        chip8_inst.set_cosmac();
+    let mut debugger = if args.debug {
+        Some(Debugger::open(&video_subsys)?)
     }
+    else {
+        None
+    };
+
+    let mut event_pump = sdl_context.event_pump()?;
+
+    let mut history = FrameHistory::new(args.filter);
+    let mut overlay = Overlay::new(args.stats);
 
-    let mut rom = File::open(&args[1]).expect("Unable to open file");
-    let mut buffer = Vec::new();
-    rom.read_to_end(&mut buffer).unwrap();
-    chip8_inst.load(&buffer);
+    let ticks_per_frame = args.machine.ips as f64 / FPS;
+    let mut tick_budget = 0.0;
 
     'gameloop: loop {
+        let mut start_game = false;
+
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit{..} => {
                     break 'gameloop;
                 },
+                // With the debugger open, closing a window doesn't send Quit on its own
+                Event::Window{window_id, win_event: WindowEvent::Close, ..} => {
+                    if debugger.as_ref().is_some_and(|d| d.window_id() == window_id) {
+                        debugger = None;
+                    }
+                    else {
+                        break 'gameloop;
+                    }
+                },
+                Event::KeyDown{keycode: Some(Keycode::F12), repeat: false, ..} => {
+                    debugger = match debugger {
+                        Some(_) => None,
+                        None => Some(Debugger::open(&video_subsys)?),
+                    };
+                },
+                // Input to the debugger window is for editing, not for the game
+                event if debugger.as_ref().is_some_and(|d| event.get_window_id() == Some(d.window_id())) => {
+                    debugger.as_mut().unwrap().handle_event(&event, &mut chip8_inst, &mut cheats);
+                },
+                Event::ControllerDeviceAdded{which, ..} => {
+                    if let Some(subsys) = &controller_subsys {
+                        match subsys.open(which) {
+                            Ok(controller) => controllers.push(controller),
+                            Err(e) => eprintln!("warning: couldn't open gamepad: {}", e),
+                        }
+                    }
+                },
+                Event::ControllerDeviceRemoved{which, ..} => {
+                    controllers.retain(|controller| controller.instance_id() != which);
+                },
+                Event::KeyDown{keycode: Some(key), ..} if in_menu => {
+                    let menu = menu.as_mut().unwrap();
+                    match key {
+                        Keycode::Up => menu.move_by(-1),
+                        Keycode::Down => menu.move_by(1),
+                        Keycode::PageUp => menu.move_by(-10),
+                        Keycode::PageDown => menu.move_by(10),
+                        Keycode::Home => menu.move_by(isize::MIN),
+                        Keycode::End => menu.move_by(isize::MAX),
+                        Keycode::Return => start_game = true,
+                        // Only go back if there's a game to go back to
+                        Keycode::Escape if !rom.is_empty() => in_menu = false,
+                        _ => (),
+                    }
+                },
+                Event::ControllerButtonDown{button, ..} if in_menu => {
+                    let menu = menu.as_mut().unwrap();
+                    match button {
+                        Button::DPadUp => menu.move_by(-1),
+                        Button::DPadDown => menu.move_by(1),
+                        Button::LeftShoulder => menu.move_by(-10),
+                        Button::RightShoulder => menu.move_by(10),
+                        Button::A => start_game = true,
+                        Button::B if !rom.is_empty() => in_menu = false,
+                        _ => (),
+                    }
+                },
+                Event::KeyDown{keycode: Some(Keycode::Escape), ..} if menu.is_some() => {
+                    in_menu = true;
+                },
+                Event::ControllerButtonDown{button: Button::Back, ..} if menu.is_some() => {
+                    in_menu = true;
+                },
+                Event::KeyDown{keycode: Some(Keycode::F11), ..} => {
+                    toggle_fullscreen(&mut canvas);
+                },
+                Event::KeyDown{keycode: Some(Keycode::Return), keymod, ..}
+                    if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) => {
+                    toggle_fullscreen(&mut canvas);
+                },
+                Event::KeyDown{keycode: Some(Keycode::F10), ..} => {
+                    grid = !grid;
+                    overlay.show(if grid { "Grid on" } else { "Grid off" });
+                },
+                Event::KeyDown{keycode: Some(Keycode::F9), ..} => {
+                    overlay.toggle_stats();
+                },
+                Event::KeyDown{keycode: Some(Keycode::P), repeat: false, ..} => {
+                    paused = !paused;
+                    set_title(&mut canvas, paused);
+                },
+                Event::KeyDown{keycode: Some(Keycode::F5), repeat: false, ..} => {
+                    restart(&mut chip8_inst, &rom)?;
+                    tick_budget = 0.0;
+                    overlay.show("Restarted");
+                },
+                Event::DropFile{filename, ..} => {
+                    let path = Path::new(&filename);
+                    let detect = matches!(args.machine.platform.name, PlatformName::Auto);
+                    match swap_rom(&mut chip8_inst, &rom, path, detect) {
+                        Ok(new_rom) => {
+                            rom = new_rom;
+                            cheats = load_cheats(args.cheat_dir.as_deref(), &rom);
+                            keymap = pick_keymap(&user_keymap, chip8_inst.get_platform());
+                            tick_budget = 0.0;
+                            in_menu = false;
+                            overlay.show(format!("Loaded {}", file_name(path)));
+                        },
+                        Err(e) => {
+                            eprintln!("error: {}", e);
+                            overlay.show(format!("Couldn't load {}", file_name(path)));
+                        },
+                    }
+                },
+                Event::KeyDown{keycode: Some(Keycode::Tab), ..} => {
+                    let next = history.get_filter().next();
+                    history.set_filter(next);
+                    // Rebuild the frame from scratch under the new filter
+                    history.push(chip8_inst.get_display());
+                    upload_frame(&history, &mut texture, &palette, &chip8_inst, None);
+                    overlay.show(format!("Filter: {}", next.name()));
+                },
                 Event::KeyDown{keycode: Some(key), ..} => {
-                    if let Some(k) = keymap(key) {
+                    if let Some(k) = keymap.get(key) {
                         chip8_inst.keypress(k, true);
                     }
+                    if let Some(k) = keymap.get2(key) {
+                        chip8_inst.keypress2(k, true);
+                    }
                 },
                 Event::KeyUp{keycode: Some(key), ..} => {
-                    if let Some(k) = keymap(key) {
+                    if let Some(k) = keymap.get(key) {
                         chip8_inst.keypress(k, false);
                     }
+                    if let Some(k) = keymap.get2(key) {
+                        chip8_inst.keypress2(k, false);
+                    }
                 },
                 _ => ()
             }
         }
-        
-        for _ in 0..TICKS_PER_FRAME {
-            chip8_inst.tick();
+
+        if start_game {
+            if let Some(item) = menu.as_ref().and_then(|menu| menu.selected()) {
+                match launch(&args.machine, item) {
+                    Ok((new_emu, new_rom)) => {
+                        chip8_inst = new_emu;
+                        rom = new_rom;
+                        cheats = load_cheats(args.cheat_dir.as_deref(), &rom);
+                        keymap = pick_keymap(&user_keymap, chip8_inst.get_platform());
+                        tick_budget = 0.0;
+                        in_menu = false;
+                    },
+                    Err(e) => {
+                        eprintln!("error: {}", e);
+                        overlay.show(format!("Couldn't load {}", file_name(&item.path)));
+                    },
+                }
+            }
         }
-        chip8_inst.tick_timers();
-        draw_screen(&chip8_inst, &mut canvas);
+
+        if let Some(d) = debugger.as_mut() {
+            d.draw(&chip8_inst, &cheats, &palette);
+        }
+
+        if in_menu {
+            if let Some(device) = &beeper {
+                device.pause();
+                beeping = false;
+            }
+            menu.as_mut().unwrap().draw(&mut canvas, &palette);
+            overlay.draw(&mut canvas, &palette, None);
+            canvas.present();
+            continue;
+        }
+
+        let mut ticks = 0;
+        if !paused {
+            cheats.list.apply(&mut chip8_inst);
+            if args.machine.vip_timing {
+                ticks = chip8_inst.run_frame();
+            }
+            else {
+                tick_budget += ticks_per_frame;
+                while tick_budget >= 1.0 {
+                    chip8_inst.tick();
+                    tick_budget -= 1.0;
+                    ticks += 1;
+                }
+                chip8_inst.tick_timers();
+            }
+        }
+        overlay.count_frame(ticks);
+
+        if let Some(device) = &mut beeper {
+            // CHIP-8X games pick their own pitch with FXF8
+            let port = chip8_inst.get_port_out().filter(|_| chip8_inst.get_platform() == Platform::Chip8X);
+            if port != tone_port {
+                device.lock().set_tone(port.map(|port| VP595_CLOCK_HZ / (port as f32 + 1.0)));
+                tone_port = port;
+            }
+
+            // MegaChip's sampled sound plays alongside the beep
+            let mut speaker = device.lock();
+            match chip8_inst.take_sound() {
+                Some(SoundEvent::Play { samples, rate, looping }) => speaker.play_sample(&samples, rate, looping),
+                Some(SoundEvent::Stop) => speaker.stop_sample(),
+                None => (),
+            }
+
+            let (_, s_timer) = chip8_inst.get_timers();
+            speaker.set_beeping(s_timer > 0);
+            let should_beep = speaker.is_playing() && !paused;
+            drop(speaker);
+            if should_beep != beeping {
+                if should_beep {
+                    device.resume();
+                }
+                else {
+                    device.pause();
+                }
+                beeping = should_beep;
+            }
+        }
+
+        let screen_size = chip8_inst.get_screen_size();
+        let mut dirty = chip8_inst.take_dirty();
+        let mut full_upload = false;
+
+        let query = texture.query();
+        if (query.width as usize, query.height as usize) != screen_size {
+            texture = texture_creator
+                .create_texture_streaming(PixelFormatEnum::RGB24, screen_size.0 as u32, screen_size.1 as u32)
+                .map_err(|e| e.to_string())?;
+            full_upload = true;
+        }
+
+        // Filters that fade over time need every pixel refreshed each frame
+        if history.get_filter().is_temporal() {
+            full_upload = true;
+        }
+
+        if full_upload {
+            dirty = None;
+        }
+
+        // Only touch the texture when there's something new to show
+        if full_upload || dirty.is_some() {
+            history.push(chip8_inst.get_display());
+            upload_frame(&history, &mut texture, &palette, &chip8_inst, dirty);
+        }
+
+        draw_screen(&texture, &mut canvas, screen_size, scale_mode, grid, &palette);
+        let status = match chip8_inst.get_fault() {
+            Some(fault) => Some(format!("Stopped: {}", fault)),
+            None if paused => Some(String::from("PAUSED")),
+            None => None,
+        };
+        overlay.draw(&mut canvas, &palette, status.as_deref());
+        canvas.present();
     }
+
+    Ok(())
 }
 
-fn draw_screen(emu: &Emu, canvas: &mut Canvas<Window>) {
-    canvas.set_draw_color(Color::RGB(84, 49, 163));
-    canvas.clear();
+// Cheats for a newly loaded ROM. A broken cheat file shouldn't stop the game, so start with none
+fn load_cheats(dir: Option<&Path>, rom: &[u8]) -> Cheats {
+    Cheats::load(dir, rom).unwrap_or_else(|e| {
+        eprintln!("warning: {}", e);
+        Cheats::load(None, rom).unwrap()
+    })
+}
+
+// Start a ROM picked in the browser on a fresh machine, using the database's quirks if it has any
+fn launch(machine: &MachineArgs, item: &MenuItem) -> Result<(Emu, Vec<u8>), String> {
+    let rom = read_rom(&item.path)?;
+    let mut machine = machine.clone();
+    if let Some(quirks) = item.entry.quirks {
+        machine.quirks = quirks;
+    }
+    let chip8_inst = build_emu(&machine, &rom).map_err(|e| format!("{}: {}", item.path.display(), e))?;
+
+    Ok((chip8_inst, rom))
+}
 
-    let screen_buf = emu.get_display();
+// Start the current game over from scratch, keeping the quirks it was started with
+fn restart(chip8_inst: &mut Emu, rom: &[u8]) -> Result<(), String> {
+    chip8_inst.reset();
+    chip8_inst.load(rom).map_err(|e| e.to_string())
+}
+
+// Switch to the ROM at path, or carry on with the old one if it can't be loaded.
+// With detect set, the platform is worked out again for the new ROM
+fn swap_rom(chip8_inst: &mut Emu, old_rom: &[u8], path: &Path, detect: bool) -> Result<Vec<u8>, String> {
+    let rom = read_rom(path)?;
+
+    let old_platform = chip8_inst.get_platform();
+    if detect {
+        chip8_inst.set_platform(Platform::detect(&rom));
+    }
+    if let Err(e) = restart(chip8_inst, &rom) {
+        chip8_inst.set_platform(old_platform);
+        restart(chip8_inst, old_rom)?;
+        return Err(format!("{}: {}", path.display(), e));
+    }
 
-    canvas.set_draw_color(Color::RGB(123, 156, 237));
+    Ok(rom)
+}
+
+// The keymap file if there is one, otherwise the layout for the platform, which can change
+// when another ROM is loaded
+fn pick_keymap(user_keymap: &Option<Keymap<Keycode>>, platform: Platform) -> Keymap<Keycode> {
+    user_keymap.clone().unwrap_or_else(|| Keymap::for_platform(platform, Keycode::from_name))
+}
+
+// Just the file name for messages, since full paths don't fit on screen
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned()
+}
 
-    for (i, pixel) in screen_buf.iter().enumerate() {
-        if *pixel {
-            let x = (i % cpu::SCREEN_WIDTH) as u32;
-            let y = (i / cpu::SCREEN_WIDTH) as u32;
+fn disassemble(path: &Path, platform: &PlatformArgs) -> Result<(), String> {
+    let rom = read_rom(path)?;
+    let platform = platform.resolve(&rom);
 
-            let rect = Rect::new((x * SCALE) as i32, (y * SCALE) as i32, SCALE, SCALE);
-            canvas.fill_rect(rect).unwrap();
+    for (i, chunk) in rom.chunks(2).enumerate() {
+        let addr = platform.load_addr(&rom) as usize + i * 2;
+        if chunk.len() == 2 {
+            let op = u16::from_be_bytes([chunk[0], chunk[1]]);
+            println!("{:#05X}: {:04X}  {}", addr, op, disasm::disassemble(op, platform));
+        }
+        else {
+            // Odd trailing byte
+            println!("{:#05X}: {:02X}    DB {:#04X}", addr, chunk[0], chunk[0]);
         }
     }
-    canvas.present();
+
+    Ok(())
 }
 
-fn keymap(key: Keycode) -> Option<usize> {
-    match key {
-        Keycode::Num1 => Some(0x1),
-        Keycode::Num2 => Some(0x2),
-        Keycode::Num3 => Some(0x3),
-        Keycode::Num4 => Some(0xC),
-        Keycode::Q => Some(0x4),
-        Keycode::W => Some(0x5),
-        Keycode::E => Some(0x6),
-        Keycode::R => Some(0xD),
-        Keycode::A => Some(0x7),
-        Keycode::S => Some(0x8),
-        Keycode::D => Some(0x9),
-        Keycode::F => Some(0xE),
-        Keycode::Z => Some(0xA),
-        Keycode::X => Some(0x0),
-        Keycode::C => Some(0xB),
-        Keycode::V => Some(0xF),
-        _ => None,
+// There's no ROM to detect the platform from yet, so auto means plain CHIP-8
+fn assemble(source: &Path, output: &Path, platform: &PlatformArgs) -> Result<(), String> {
+    let text = fs::read_to_string(source)
+        .map_err(|e| format!("couldn't read {}: {}", source.display(), e))?;
+    let platform = platform.resolve(&[]);
+    let rom = asm::assemble_for(&text, platform).map_err(|e| format!("{}: {}", source.display(), e))?;
+
+    fs::write(output, &rom).map_err(|e| format!("couldn't write {}: {}", output.display(), e))?;
+    println!("Wrote {} bytes to {}", rom.len(), output.display());
+
+    Ok(())
+}
+
+fn info(path: &Path, platform: &PlatformArgs) -> Result<(), String> {
+    let rom = read_rom(path)?;
+
+    println!("File: {}", path.display());
+    print!("{}", RomInfo::inspect(&rom, platform.resolve(&rom)));
+
+    Ok(())
+}
+
+// Run headless for a fixed number of frames, handy for checking test ROMs
+fn test(args: TestArgs) -> Result<(), String> {
+    let rom = read_rom(&args.rom)?;
+    let mut chip8_inst = build_emu(&args.machine, &rom)?;
+
+    let ticks_per_frame = args.machine.ips as f64 / FPS;
+    let mut tick_budget = 0.0;
+
+    for _ in 0..args.frames {
+        if args.machine.vip_timing {
+            chip8_inst.run_frame();
+            continue;
+        }
+        tick_budget += ticks_per_frame;
+        let ticks = tick_budget as u32;
+        #[cfg(feature = "jit")]
+        if args.jit {
+            chip8_inst.run_jit(ticks);
+        }
+        else {
+            chip8_inst.run_ticks(ticks);
+        }
+        #[cfg(not(feature = "jit"))]
+        chip8_inst.run_ticks(ticks);
+        tick_budget -= ticks as f64;
+        chip8_inst.tick_timers();
     }
-}
\ No newline at end of file
+
+    let (screen_w, screen_h) = chip8_inst.get_screen_size();
+    for y in 0..screen_h {
+        let line: String = (0..screen_w).map(|x| if chip8_inst.get_pixel(x, y) { '#' } else { '.' }).collect();
+        println!("{}", line);
+    }
+
+    // Fail so scripts notice the ROM crashed
+    if let Some(fault) = chip8_inst.get_fault() {
+        return Err(format!("machine stopped: {}", fault));
+    }
+
+    Ok(())
+}
+
+fn set_title(canvas: &mut Canvas<Window>, paused: bool) {
+    let title = if paused { "yachip8emu (paused)" } else { "yachip8emu" };
+    // Only fails if the title has a nul byte in it
+    let _ = canvas.window_mut().set_title(title);
+}
+
+fn toggle_fullscreen(canvas: &mut Canvas<Window>) {
+    let window = canvas.window_mut();
+    let next = match window.fullscreen_state() {
+        FullscreenType::Off => FullscreenType::Desktop,
+        _ => FullscreenType::Off,
+    };
+    window.set_fullscreen(next).unwrap();
+}
+
+// Convert the filtered framebuffer into texture pixels, limited to the changed area if given.
+// CHIP-8X colours, when there are any, take the place of the palette, and a MegaChip
+// frame is shown as it is
+fn upload_frame(
+    history: &FrameHistory,
+    texture: &mut Texture,
+    palette: &Palette,
+    emu: &Emu,
+    area: Option<DirtyRect>,
+) {
+    let query = texture.query();
+    let screen_w = query.width as usize;
+    let area = area.unwrap_or(DirtyRect {
+        x: 0,
+        y: 0,
+        width: screen_w,
+        height: query.height as usize,
+    });
+    let intensity = history.get_intensity();
+    let colors = emu.get_colors();
+    let frame = emu.get_frame();
+
+    let lock_rect = Rect::new(area.x as i32, area.y as i32, area.width as u32, area.height as u32);
+    texture.with_lock(Some(lock_rect), |buffer: &mut [u8], pitch: usize| {
+        // The locked buffer starts at the top left corner of the area
+        for row in 0..area.height {
+            for col in 0..area.width {
+                let level = intensity[(area.y + row) * screen_w + area.x + col];
+                let offset = row * pitch + col * 3;
+
+                if let Some(frame) = frame {
+                    let rgb = frame[(area.y + row) * screen_w + area.x + col];
+                    buffer[offset] = (rgb >> 16) as u8;
+                    buffer[offset + 1] = (rgb >> 8) as u8;
+                    buffer[offset + 2] = rgb as u8;
+                    continue;
+                }
+
+                let color = match colors {
+                    Some((bg, zones)) => {
+                        let zone = zones[(area.y + row) * (screen_w / ZONE_WIDTH) + (area.x + col) / ZONE_WIDTH];
+                        Palette { bg: vp590_color(bg), fg: vp590_color(zone) }.blend(level)
+                    },
+                    None => palette.blend(level),
+                };
+                buffer[offset] = color.r;
+                buffer[offset + 1] = color.g;
+                buffer[offset + 2] = color.b;
+            }
+        }
+    }).unwrap();
+}
+
+fn draw_screen(
+    texture: &Texture,
+    canvas: &mut Canvas<Window>,
+    screen_size: (usize, usize),
+    scale_mode: ScaleMode,
+    grid: bool,
+    palette: &Palette,
+) {
+    // Letterbox bars
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.clear();
+
+    let (screen_w, screen_h) = screen_size;
+    let view = video::viewport(canvas.output_size().unwrap(), screen_size, scale_mode);
+
+    // Let the GPU scale the framebuffer up to the viewport
+    canvas.copy(texture, None, view).unwrap();
+
+    if grid {
+        let px = |x: usize| view.x() + (x as u32 * view.width() / screen_w as u32) as i32;
+        let py = |y: usize| view.y() + (y as u32 * view.height() / screen_h as u32) as i32;
+
+        canvas.set_draw_color(palette.grid());
+        for x in 1..screen_w {
+            canvas.draw_line(Point::new(px(x), view.top()), Point::new(px(x), view.bottom() - 1)).unwrap();
+        }
+        for y in 1..screen_h {
+            canvas.draw_line(Point::new(view.left(), py(y)), Point::new(view.right() - 1, py(y))).unwrap();
+        }
+    }
+}
@@ -0,0 +1,266 @@
+// MegaChip-8's colour display, palette and sampled sound.
+//
+// Once 0011 switches a program into MegaChip mode, sprites are bytes of palette indices
+// blended into a 256x192 buffer, and 00E0 puts that buffer on screen and starts a fresh
+// one. Index 0 is transparent, and drawing over the collision colour sets VF.
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 192;
+// I takes 24-bit addresses in MegaChip mode
+pub const MEM_SIZE: usize = 0x100_0000;
+const PALETTE_SIZE: usize = 256;
+// Sampled sound starts with its rate in Hz, a 24-bit length and a reserved byte
+const SAMPLE_HEADER_SIZE: usize = 6;
+// Size in bytes of everything save writes
+pub const STATE_SIZE: usize = PALETTE_SIZE * 4 + 2 + 2 + 1 + 1 + 1 + WIDTH * HEIGHT * 9;
+
+// How 080N mixes sprite pixels with what's already on screen
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Blend {
+    Normal,
+    // The sprite at 25%, 50% or 75% opacity
+    Quarter,
+    Half,
+    ThreeQuarters,
+    Add,
+    Multiply,
+}
+
+impl Blend {
+    fn from_mode(mode: u8) -> Blend {
+        match mode {
+            1 => Blend::Quarter,
+            2 => Blend::Half,
+            3 => Blend::ThreeQuarters,
+            4 => Blend::Add,
+            5 => Blend::Multiply,
+            _ => Blend::Normal,
+        }
+    }
+
+    fn mode(&self) -> u8 {
+        match self {
+            Blend::Normal => 0,
+            Blend::Quarter => 1,
+            Blend::Half => 2,
+            Blend::ThreeQuarters => 3,
+            Blend::Add => 4,
+            Blend::Multiply => 5,
+        }
+    }
+
+    // Mix two 0xRRGGBB colours a channel at a time
+    fn apply(&self, under: u32, over: u32) -> u32 {
+        let mix = |under: u32, over: u32| -> u32 {
+            match self {
+                Blend::Normal => over,
+                Blend::Quarter => (under * 3 + over) / 4,
+                Blend::Half => (under + over) / 2,
+                Blend::ThreeQuarters => (under + over * 3) / 4,
+                Blend::Add => (under + over).min(0xFF),
+                Blend::Multiply => under * over / 0xFF,
+            }
+        };
+
+        [16, 8, 0].iter().fold(0, |color, shift| {
+            color | mix((under >> shift) & 0xFF, (over >> shift) & 0xFF) << shift
+        })
+    }
+}
+
+// What the frontend should do with the sampled sound channel
+#[derive(Clone, Debug, PartialEq)]
+pub enum SoundEvent {
+    // Unsigned 8-bit samples
+    Play { samples: Vec<u8>, rate: u32, looping: bool },
+    Stop,
+}
+
+pub struct MegaChip {
+    // ARGB colours loaded by 02NN, starting at index 1
+    palette: [u32; PALETTE_SIZE],
+    sprite_width: usize,
+    sprite_height: usize,
+    // 05NN: how much of the finished frame shows through over black
+    screen_alpha: u8,
+    blend: Blend,
+    collision_color: u8,
+    // Palette index last drawn at each pixel, which collisions are checked against
+    indices: Vec<u8>,
+    // Sprites are drawn here until 00E0 copies it to the front
+    back: Vec<u32>,
+    front: Vec<u32>,
+    sound: Option<SoundEvent>,
+}
+
+impl Default for MegaChip {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MegaChip {
+    pub fn new() -> Self {
+        MegaChip {
+            palette: [0; PALETTE_SIZE],
+            sprite_width: 0,
+            sprite_height: 0,
+            screen_alpha: 0xFF,
+            blend: Blend::Normal,
+            collision_color: 0,
+            indices: vec![0; WIDTH * HEIGHT],
+            back: vec![0; WIDTH * HEIGHT],
+            front: vec![0; WIDTH * HEIGHT],
+            sound: None,
+        }
+    }
+
+    // 02NN: Load count ARGB colours from mem at addr into the palette, from index 1 up
+    pub fn load_palette(&mut self, mem: &[u8], addr: usize, count: usize) {
+        for (i, color) in self.palette[1..].iter_mut().take(count).enumerate() {
+            let bytes: Vec<u8> = (0..4).map(|byte| read(mem, addr + i * 4 + byte)).collect();
+            *color = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+    }
+
+    // 03NN and 04NN, where 0 means 256
+    pub fn set_sprite_width(&mut self, width: u8) {
+        self.sprite_width = if width == 0 { 256 } else { width as usize };
+    }
+
+    pub fn set_sprite_height(&mut self, height: u8) {
+        self.sprite_height = if height == 0 { 256 } else { height as usize };
+    }
+
+    pub fn set_screen_alpha(&mut self, alpha: u8) {
+        self.screen_alpha = alpha;
+    }
+
+    pub fn set_blend(&mut self, mode: u8) {
+        self.blend = Blend::from_mode(mode);
+    }
+
+    pub fn set_collision_color(&mut self, index: u8) {
+        self.collision_color = index;
+    }
+
+    // DXYN in MegaChip mode: draw the sprite at addr with its top left corner at x, y,
+    // clipped to the screen. Returns whether it covered the collision colour
+    pub fn blit(&mut self, mem: &[u8], addr: usize, x: usize, y: usize) -> bool {
+        let mut collision = false;
+
+        for row in 0..self.sprite_height {
+            let py = y + row;
+            if py >= HEIGHT {
+                break;
+            }
+            for col in 0..self.sprite_width {
+                let px = x + col;
+                if px >= WIDTH {
+                    break;
+                }
+
+                let index = read(mem, addr + row * self.sprite_width + col);
+                if index == 0 {
+                    continue;
+                }
+
+                let pixel = py * WIDTH + px;
+                collision |= self.indices[pixel] == self.collision_color;
+                self.indices[pixel] = index;
+                self.back[pixel] = self.blend.apply(self.back[pixel], self.palette[index as usize] & 0xFF_FFFF);
+            }
+        }
+
+        collision
+    }
+
+    // 00BN: Move the picture being drawn up n rows, leaving black at the bottom
+    pub fn scroll_up(&mut self, n: usize) {
+        let n = n.min(HEIGHT) * WIDTH;
+        self.indices.copy_within(n.., 0);
+        self.back.copy_within(n.., 0);
+        let len = self.back.len();
+        self.indices[len - n..].fill(0);
+        self.back[len - n..].fill(0);
+    }
+
+    // 00E0: Show what's been drawn, faded by the screen alpha, and start on an empty frame
+    pub fn show(&mut self) {
+        let alpha = self.screen_alpha as u32;
+        for (front, back) in self.front.iter_mut().zip(&self.back) {
+            *front = [16, 8, 0].iter().fold(0, |color, shift| {
+                color | (((*back >> shift) & 0xFF) * alpha / 0xFF) << shift
+            });
+        }
+        self.back.fill(0);
+        self.indices.fill(0);
+    }
+
+    // The picture on screen as 0xRRGGBB
+    pub fn frame(&self) -> &[u32] {
+        &self.front
+    }
+
+    // 060N: Play the sampled sound at addr, over and over if looping
+    pub fn play(&mut self, mem: &[u8], addr: usize, looping: bool) {
+        let header: Vec<u8> = (0..SAMPLE_HEADER_SIZE).map(|byte| read(mem, addr + byte)).collect();
+        let rate = u16::from_be_bytes([header[0], header[1]]) as u32;
+        let len = u32::from_be_bytes([0, header[2], header[3], header[4]]) as usize;
+
+        let start = (addr + SAMPLE_HEADER_SIZE).min(mem.len());
+        let end = (start + len).min(mem.len());
+        self.sound = Some(SoundEvent::Play { samples: mem[start..end].to_vec(), rate, looping });
+    }
+
+    // 0700
+    pub fn stop(&mut self) {
+        self.sound = Some(SoundEvent::Stop);
+    }
+
+    // The last sound started or stopped since the frontend last looked
+    pub fn take_sound(&mut self) -> Option<SoundEvent> {
+        self.sound.take()
+    }
+
+    pub fn save(&self, state: &mut Vec<u8>) {
+        for color in &self.palette {
+            state.extend_from_slice(&color.to_be_bytes());
+        }
+        state.extend_from_slice(&(self.sprite_width as u16).to_be_bytes());
+        state.extend_from_slice(&(self.sprite_height as u16).to_be_bytes());
+        state.push(self.screen_alpha);
+        state.push(self.blend.mode());
+        state.push(self.collision_color);
+        state.extend_from_slice(&self.indices);
+        for color in self.back.iter().chain(&self.front) {
+            state.extend_from_slice(&color.to_be_bytes());
+        }
+    }
+
+    // Restore from STATE_SIZE bytes written by save
+    pub fn load(&mut self, state: &[u8]) {
+        let (palette, rest) = state.split_at(PALETTE_SIZE * 4);
+        for (color, bytes) in self.palette.iter_mut().zip(palette.chunks(4)) {
+            *color = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        self.sprite_width = u16::from_be_bytes([rest[0], rest[1]]) as usize;
+        self.sprite_height = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+        self.screen_alpha = rest[4];
+        self.blend = Blend::from_mode(rest[5]);
+        self.collision_color = rest[6];
+
+        let (indices, colors) = rest[7..].split_at(WIDTH * HEIGHT);
+        self.indices.copy_from_slice(indices);
+        let mut colors = colors.chunks(4).map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+        for color in self.back.iter_mut().chain(self.front.iter_mut()) {
+            *color = colors.next().unwrap();
+        }
+        self.sound = None;
+    }
+}
+
+// Reads past the end of memory come back as 0
+fn read(mem: &[u8], addr: usize) -> u8 {
+    mem.get(addr).copied().unwrap_or(0)
+}
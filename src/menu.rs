@@ -0,0 +1,153 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+
+use sdl2::rect::Rect;
+use sdl2::render::Canvas;
+use sdl2::video::Window;
+
+use yachip8emu::cpu::Platform;
+use yachip8emu::rom::{sha1_hex, RomInfo};
+
+use crate::romdb::{RomDb, RomEntry};
+use crate::text;
+use crate::video::Palette;
+
+const EXTENSIONS: [&str; 3] = ["ch8", "sc8", "xo8"];
+// Lines taken up by the header, details panel and footer
+const RESERVED_LINES: u32 = 9;
+
+pub struct MenuItem {
+    pub path: PathBuf,
+    pub name: String,
+    pub entry: RomEntry,
+    // Guessed from the opcodes when the database doesn't say
+    pub platform: String,
+}
+
+pub struct Menu {
+    dir: PathBuf,
+    items: Vec<MenuItem>,
+    selected: usize,
+    // First item shown at the top of the list
+    scroll: usize,
+}
+
+impl Menu {
+    // List the ROMs in dir, filling in whatever the database knows about each one
+    pub fn scan(dir: &Path, db: &RomDb) -> Result<Menu, String> {
+        let listing = fs::read_dir(dir)
+            .map_err(|e| format!("couldn't read ROM directory {}: {}", dir.display(), e))?;
+        let mut items = Vec::new();
+
+        for dir_entry in listing.flatten() {
+            let path = dir_entry.path();
+            let is_rom = path.extension()
+                .and_then(|ext| ext.to_str())
+                .is_some_and(|ext| EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+            if !is_rom {
+                continue;
+            }
+
+            // Skip anything unreadable rather than refusing to show the rest
+            let rom = match fs::read(&path) {
+                Ok(rom) => rom,
+                Err(_) => continue,
+            };
+            let entry = db.get(&sha1_hex(&rom)).cloned().unwrap_or_default();
+            let platform = match &entry.platform {
+                Some(platform) => platform.clone(),
+                None => format!("{} (guessed)", RomInfo::inspect(&rom, Platform::detect(&rom)).variant),
+            };
+            let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+            let name = entry.title.clone().unwrap_or(file_name);
+
+            items.push(MenuItem { path, name, entry, platform });
+        }
+
+        items.sort_by_key(|item| item.name.to_lowercase());
+
+        Ok(Menu { dir: dir.to_path_buf(), items, selected: 0, scroll: 0 })
+    }
+
+    pub fn selected(&self) -> Option<&MenuItem> {
+        self.items.get(self.selected)
+    }
+
+    // Move the highlight up (negative) or down, stopping at either end
+    pub fn move_by(&mut self, delta: isize) {
+        if self.items.is_empty() {
+            return;
+        }
+        let last = self.items.len() as isize - 1;
+        self.selected = (self.selected as isize).saturating_add(delta).clamp(0, last) as usize;
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas<Window>, palette: &Palette) {
+        let (out_w, out_h) = canvas.output_size().unwrap();
+        // Aim for about 30 lines of text whatever the window size
+        let scale = (out_h / (30 * text::LINE_HEIGHT)).max(1);
+        let line = (text::LINE_HEIGHT * scale) as i32;
+        let margin = (text::ADVANCE * scale) as i32;
+        let dim = palette.blend(0.5);
+
+        canvas.set_draw_color(palette.bg);
+        canvas.clear();
+
+        let mut y = margin;
+        let header = format!("ROMs in {}", self.dir.display());
+        text::draw_text(canvas, &header, margin, y, scale, palette.fg);
+        y += line * 2;
+
+        let rows = ((out_h / (line as u32)).saturating_sub(RESERVED_LINES) as usize).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        }
+        else if self.selected >= self.scroll + rows {
+            self.scroll = self.selected + 1 - rows;
+        }
+
+        if self.items.is_empty() {
+            text::draw_text(canvas, "No .ch8, .sc8 or .xo8 files found", margin, y, scale, dim);
+        }
+
+        for (i, item) in self.items.iter().enumerate().skip(self.scroll).take(rows) {
+            let row_y = y + (i - self.scroll) as i32 * line;
+            let color = if i == self.selected {
+                canvas.set_draw_color(palette.fg);
+                let bar = Rect::new(0, row_y - scale as i32, out_w, line as u32);
+                canvas.fill_rect(bar).unwrap();
+                palette.bg
+            }
+            else {
+                palette.fg
+            };
+            text::draw_text(canvas, &item.name, margin, row_y, scale, color);
+        }
+
+        // Details of the highlighted ROM, then the controls, along the bottom
+        if let Some(item) = self.selected() {
+            let unknown = String::from("unknown");
+            let quirks = item.entry.quirks
+                .and_then(|preset| preset.to_possible_value())
+                .map(|value| value.get_name().to_string())
+                .unwrap_or(String::from("default"));
+            let details = [
+                format!("File:     {}", item.path.file_name().unwrap().to_string_lossy()),
+                format!("Author:   {}", item.entry.author.as_ref().unwrap_or(&unknown)),
+                format!("Platform: {}", item.platform),
+                format!("Quirks:   {}", quirks),
+            ];
+
+            let mut detail_y = out_h as i32 - line * 6 - margin;
+            for detail in &details {
+                text::draw_text(canvas, detail, margin, detail_y, scale, palette.fg);
+                detail_y += line;
+            }
+        }
+
+        let footer = "Up/Down: choose  Enter/A: play  Esc/B: back";
+        text::draw_text(canvas, footer, margin, out_h as i32 - line - margin, scale, dim);
+    }
+}
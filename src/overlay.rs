@@ -0,0 +1,108 @@
+use std::time::{Duration, Instant};
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::video::Window;
+
+use crate::text;
+use crate::video::Palette;
+
+// How long a status message stays up
+const MESSAGE_TIME: Duration = Duration::from_secs(2);
+// How often the FPS and IPS counters are recalculated
+const STATS_INTERVAL: Duration = Duration::from_millis(500);
+// Translucent box behind text so it stays readable over any screen contents
+const BACKING: Color = Color::RGBA(0, 0, 0, 160);
+
+// Text drawn over the emulated display: status messages, counters and whether the machine is stopped
+pub struct Overlay {
+    message: Option<(String, Instant)>,
+    show_stats: bool,
+    frames: u32,
+    instructions: u64,
+    stats_start: Instant,
+    fps: f64,
+    ips: f64,
+}
+
+impl Overlay {
+    pub fn new(show_stats: bool) -> Self {
+        Overlay {
+            message: None,
+            show_stats,
+            frames: 0,
+            instructions: 0,
+            stats_start: Instant::now(),
+            fps: 0.0,
+            ips: 0.0,
+        }
+    }
+
+    // Replace whatever message is showing
+    pub fn show(&mut self, message: impl Into<String>) {
+        self.message = Some((message.into(), Instant::now()));
+    }
+
+    pub fn toggle_stats(&mut self) {
+        self.show_stats = !self.show_stats;
+    }
+
+    // Call once per displayed frame with the number of instructions it ran
+    pub fn count_frame(&mut self, instructions: u32) {
+        self.frames += 1;
+        self.instructions += instructions as u64;
+
+        let elapsed = self.stats_start.elapsed();
+        if elapsed >= STATS_INTERVAL {
+            let secs = elapsed.as_secs_f64();
+            self.fps = self.frames as f64 / secs;
+            self.ips = self.instructions as f64 / secs;
+            self.frames = 0;
+            self.instructions = 0;
+            self.stats_start = Instant::now();
+        }
+    }
+
+    // status stays in the corner for as long as it's given, for things like being paused
+    pub fn draw(&mut self, canvas: &mut Canvas<Window>, palette: &Palette, status: Option<&str>) {
+        if let Some((_, shown_at)) = &self.message {
+            if shown_at.elapsed() > MESSAGE_TIME {
+                self.message = None;
+            }
+        }
+
+        let (out_w, out_h) = canvas.output_size().unwrap();
+        let scale = (out_h / 240).max(1);
+        let pad = (2 * scale) as i32;
+        let line = (text::LINE_HEIGHT * scale) as i32;
+
+        canvas.set_blend_mode(BlendMode::Blend);
+
+        if self.show_stats {
+            let stats = format!("{:.0} FPS  {:.0} IPS", self.fps, self.ips);
+            draw_label(canvas, &stats, pad, pad, scale, palette.fg);
+        }
+
+        if let Some(label) = status {
+            let x = out_w as i32 - text::text_width(label, scale) as i32 - pad * 2;
+            draw_label(canvas, label, x, pad, scale, palette.fg);
+        }
+
+        if let Some((message, _)) = &self.message {
+            draw_label(canvas, message, pad, out_h as i32 - line - pad, scale, palette.fg);
+        }
+
+        canvas.set_blend_mode(BlendMode::None);
+    }
+}
+
+fn draw_label(canvas: &mut Canvas<Window>, label: &str, x: i32, y: i32, scale: u32, color: Color) {
+    let pad = scale as i32;
+    let width = text::text_width(label, scale) + scale;
+    let height = text::GLYPH_HEIGHT * scale + scale * 2;
+
+    canvas.set_draw_color(BACKING);
+    canvas.fill_rect(Rect::new(x, y, width, height)).unwrap();
+    text::draw_text(canvas, label, x + pad, y + pad, scale, color);
+}
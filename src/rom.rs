@@ -0,0 +1,263 @@
+// Static inspection of a ROM before (or instead of) running it
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+use sha1::{Digest, Sha1};
+
+use crate::cpu::Platform;
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Variant {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Variant::Chip8 => write!(f, "CHIP-8"),
+            Variant::SuperChip => write!(f, "SUPER-CHIP"),
+            Variant::XoChip => write!(f, "XO-CHIP"),
+        }
+    }
+}
+
+// Instructions whose behaviour differs between interpreters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quirk {
+    // 8XY6/8XYE: whether VY is copied into VX before shifting
+    Shift,
+    // BNNN: whether the offset comes from V0 or VX
+    Jump,
+    // FX55/FX65: whether I is left pointing past the last register
+    LoadStore,
+}
+
+impl fmt::Display for Quirk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Quirk::Shift => write!(f, "shift (8XY6/8XYE)"),
+            Quirk::Jump => write!(f, "jump with offset (BNNN)"),
+            Quirk::LoadStore => write!(f, "load/store (FX55/FX65)"),
+        }
+    }
+}
+
+pub struct RomInfo {
+    pub size: usize,
+    pub sha1: String,
+    // Where the platform loads the ROM, and how much of it fits in memory from there
+    pub start_addr: u16,
+    pub max_size: usize,
+    // Set when the ROM is too big to load
+    pub too_large: bool,
+    pub variant: Variant,
+    // Bytes found by following the program's control flow from the start address
+    pub reachable_bytes: usize,
+    // Address and opcode of every instruction we reached but couldn't decode
+    pub undecodable: Vec<(u16, u16)>,
+    pub quirks: Vec<(Quirk, Vec<u16>)>,
+    // BNNN jumps to an address only known at runtime, so reachability is a lower bound
+    pub computed_jumps: bool,
+}
+
+impl RomInfo {
+    pub fn inspect(rom: &[u8], platform: Platform) -> RomInfo {
+        let start_addr = platform.load_addr(rom);
+        let max_size = platform.mem_size() - start_addr as usize;
+        let mut info = RomInfo {
+            size: rom.len(),
+            sha1: sha1_hex(rom),
+            start_addr,
+            max_size,
+            too_large: rom.len() > max_size,
+            variant: Variant::Chip8,
+            reachable_bytes: 0,
+            undecodable: Vec::new(),
+            quirks: vec![(Quirk::Shift, Vec::new()), (Quirk::Jump, Vec::new()), (Quirk::LoadStore, Vec::new())],
+            computed_jumps: false,
+        };
+
+        let reachable = info.trace_flow(rom);
+        info.reachable_bytes = reachable.len() * 2;
+
+        for addr in reachable {
+            let op = info.read_op(rom, addr).unwrap();
+
+            match classify(op) {
+                Some(variant) => {
+                    if variant > info.variant {
+                        info.variant = variant;
+                    }
+                },
+                None => info.undecodable.push((addr, op)),
+            }
+
+            if let Some(quirk) = quirk_for(op) {
+                for (kind, addrs) in info.quirks.iter_mut() {
+                    if *kind == quirk {
+                        addrs.push(addr);
+                    }
+                }
+            }
+        }
+
+        info
+    }
+
+    // Walk every path through the program, returning the address of each instruction found
+    fn trace_flow(&mut self, rom: &[u8]) -> BTreeSet<u16> {
+        let mut seen = BTreeSet::new();
+        let mut pending = vec![self.start_addr];
+
+        while let Some(addr) = pending.pop() {
+            let op = match self.read_op(rom, addr) {
+                Some(op) => op,
+                None => continue,
+            };
+            if !seen.insert(addr) {
+                continue;
+            }
+
+            // Running off the top of the address space ends up below the program, where
+            // there's nothing to read
+            let next = addr.wrapping_add(2);
+            let nnn = op & 0x0FFF;
+
+            match op & 0xF000 {
+                // Return or exit ends this path
+                0x0000 if op == 0x00EE || op == 0x00FD => (),
+                0x1000 => pending.push(nnn),
+                0x2000 => {
+                    pending.push(nnn);
+                    pending.push(next);
+                },
+                0xB000 => self.computed_jumps = true,
+                // Conditional skips can land on either of the next two instructions
+                0x3000 | 0x4000 | 0x5000 | 0x9000 => {
+                    pending.push(next);
+                    pending.push(next.wrapping_add(2));
+                },
+                0xE000 if classify(op).is_some() => {
+                    pending.push(next);
+                    pending.push(next.wrapping_add(2));
+                },
+                _ => {
+                    // Don't run off into data after something we can't decode
+                    if classify(op).is_some() {
+                        pending.push(next);
+                    }
+                }
+            }
+        }
+
+        seen
+    }
+
+    fn read_op(&self, rom: &[u8], addr: u16) -> Option<u16> {
+        let offset = addr.checked_sub(self.start_addr)? as usize;
+        // Anything past the end of memory would never be loaded
+        if offset + 1 >= self.max_size {
+            return None;
+        }
+        let hi = *rom.get(offset)?;
+        let lo = *rom.get(offset + 1)?;
+        Some(u16::from_be_bytes([hi, lo]))
+    }
+}
+
+impl fmt::Display for RomInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Size: {} bytes (at most {} fit from {:#05X})", self.size, self.max_size, self.start_addr)?;
+        if self.too_large {
+            writeln!(f, "  ROM is too large to load")?;
+        }
+        writeln!(f, "SHA-1: {}", self.sha1)?;
+        writeln!(f, "Platform: {} (guessed from the opcodes used)", self.variant)?;
+
+        write!(f, "Reachable code: {} bytes", self.reachable_bytes)?;
+        let unreached = self.size.saturating_sub(self.reachable_bytes);
+        if unreached > 0 {
+            write!(f, ", {} bytes never executed (probably data)", unreached)?;
+        }
+        writeln!(f)?;
+        if self.computed_jumps {
+            writeln!(f, "  uses BNNN, so some code may only be reached at runtime")?;
+        }
+
+        if !self.undecodable.is_empty() {
+            writeln!(f, "Undecodable opcodes:")?;
+            for (addr, op) in &self.undecodable {
+                writeln!(f, "  {:#05X}: {:04X}", addr, op)?;
+            }
+        }
+
+        let used: Vec<_> = self.quirks.iter().filter(|(_, addrs)| !addrs.is_empty()).collect();
+        if !used.is_empty() {
+            writeln!(f, "Quirk-sensitive instructions (results depend on --quirks):")?;
+            for (quirk, addrs) in used {
+                let list: Vec<_> = addrs.iter().map(|addr| format!("{:#05X}", addr)).collect();
+                writeln!(f, "  {}: {}", quirk, list.join(", "))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Work out the earliest platform that understands an opcode, or None if nothing does
+fn classify(op: u16) -> Option<Variant> {
+    let x = (op & 0x0F00) >> 8;
+    let low = op & 0x00FF;
+
+    match op & 0xF000 {
+        0x0000 => match op {
+            0x00E0 | 0x00EE => Some(Variant::Chip8),
+            0x00FB..=0x00FF => Some(Variant::SuperChip),
+            _ if op & 0xFFF0 == 0x00C0 => Some(Variant::SuperChip),
+            _ if op & 0xFFF0 == 0x00D0 => Some(Variant::XoChip),
+            // Machine code call, only meaningful on real hardware
+            _ => Some(Variant::Chip8),
+        },
+        0x5000 => match op & 0x000F {
+            0 => Some(Variant::Chip8),
+            2 | 3 => Some(Variant::XoChip),
+            _ => None,
+        },
+        0x8000 => match op & 0x000F {
+            0..=7 | 0xE => Some(Variant::Chip8),
+            _ => None,
+        },
+        0x9000 if op & 0x000F == 0 => Some(Variant::Chip8),
+        0x9000 => None,
+        0xD000 if op & 0x000F == 0 => Some(Variant::SuperChip),
+        0xE000 => match low {
+            0x9E | 0xA1 => Some(Variant::Chip8),
+            _ => None,
+        },
+        0xF000 => match low {
+            0x07 | 0x0A | 0x15 | 0x18 | 0x1E | 0x29 | 0x33 | 0x55 | 0x65 => Some(Variant::Chip8),
+            0x30 | 0x75 | 0x85 => Some(Variant::SuperChip),
+            0x00 if x == 0 => Some(Variant::XoChip),
+            0x01 | 0x3A => Some(Variant::XoChip),
+            0x02 if x == 0 => Some(Variant::XoChip),
+            _ => None,
+        },
+        _ => Some(Variant::Chip8),
+    }
+}
+
+fn quirk_for(op: u16) -> Option<Quirk> {
+    match (op & 0xF000, op & 0x00FF) {
+        (0x8000, low) if low & 0x0F == 0x6 || low & 0x0F == 0xE => Some(Quirk::Shift),
+        (0xB000, _) => Some(Quirk::Jump),
+        (0xF000, 0x55) | (0xF000, 0x65) => Some(Quirk::LoadStore),
+        _ => None,
+    }
+}
+
+pub fn sha1_hex(data: &[u8]) -> String {
+    Sha1::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
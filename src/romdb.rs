@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use yachip8emu::args::QuirkPreset;
+
+// What we know about a ROM beyond its contents
+#[derive(Clone, Default)]
+pub struct RomEntry {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub platform: Option<String>,
+    pub quirks: Option<QuirkPreset>,
+}
+
+// ROM metadata keyed by the SHA-1 of the file
+#[derive(Default)]
+pub struct RomDb {
+    entries: HashMap<String, RomEntry>,
+}
+
+impl RomDb {
+    // Read sections headed by a ROM's SHA-1 in square brackets, each followed by
+    // `title`, `author`, `platform` or `quirks` lines of the form `key = value`.
+    // Blank lines and lines starting with # are ignored
+    pub fn load(path: &Path) -> Result<RomDb, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("couldn't read ROM database {}: {}", path.display(), e))?;
+        let mut entries = HashMap::new();
+        let mut current: Option<(String, RomEntry)> = None;
+
+        for (i, raw) in text.lines().enumerate() {
+            let line = raw.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("{} line {}: {}", path.display(), i + 1, message);
+
+            if let Some(hash) = line.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+                if let Some((hash, entry)) = current.take() {
+                    entries.insert(hash, entry);
+                }
+                current = Some((hash.trim().to_lowercase(), RomEntry::default()));
+                continue;
+            }
+
+            let (_, entry) = current.as_mut().ok_or_else(|| error("expected a [sha1] section first"))?;
+            let (key, value) = line.split_once('=').ok_or_else(|| error("expected `key = value`"))?;
+            let value = value.trim().to_string();
+
+            match key.trim() {
+                "title" => entry.title = Some(value),
+                "author" => entry.author = Some(value),
+                "platform" => entry.platform = Some(value),
+                "quirks" => {
+                    let preset = QuirkPreset::from_str(&value, true)
+                        .map_err(|_| error(&format!("unknown quirk preset '{}'", value)))?;
+                    entry.quirks = Some(preset);
+                },
+                other => return Err(error(&format!("unknown key '{}'", other))),
+            }
+        }
+
+        if let Some((hash, entry)) = current {
+            entries.insert(hash, entry);
+        }
+
+        Ok(RomDb { entries })
+    }
+
+    pub fn get(&self, sha1: &str) -> Option<&RomEntry> {
+        self.entries.get(sha1)
+    }
+}
@@ -0,0 +1,105 @@
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ScaleMode {
+    // Largest whole-number multiple of the emulated resolution that fits
+    Integer,
+    // Fill as much of the window as possible while keeping the aspect ratio
+    Aspect,
+}
+
+impl ScaleMode {
+    pub fn from_name(name: &str) -> Option<ScaleMode> {
+        match name {
+            "integer" => Some(ScaleMode::Integer),
+            "aspect" => Some(ScaleMode::Aspect),
+            _ => None,
+        }
+    }
+}
+
+// Work out where the emulated screen goes inside the window, letterboxing the rest
+pub fn viewport(output: (u32, u32), screen: (usize, usize), mode: ScaleMode) -> Rect {
+    let (out_w, out_h) = output;
+    let screen_w = screen.0 as u32;
+    let screen_h = screen.1 as u32;
+
+    let (view_w, view_h) = match mode {
+        ScaleMode::Integer => {
+            let scale = (out_w / screen_w).min(out_h / screen_h).max(1);
+            (screen_w * scale, screen_h * scale)
+        },
+        ScaleMode::Aspect => {
+            if out_w * screen_h > out_h * screen_w {
+                // Window is wider than the screen, so bars go on the sides
+                (out_h * screen_w / screen_h, out_h)
+            }
+            else {
+                (out_w, out_w * screen_h / screen_w)
+            }
+        },
+    };
+
+    let x = (out_w as i32 - view_w as i32) / 2;
+    let y = (out_h as i32 - view_h as i32) / 2;
+
+    Rect::new(x, y, view_w.max(1), view_h.max(1))
+}
+
+#[derive(Clone, Copy)]
+pub struct Palette {
+    pub bg: Color,
+    pub fg: Color,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            bg: Color::RGB(84, 49, 163),
+            fg: Color::RGB(123, 156, 237),
+        }
+    }
+}
+
+impl Palette {
+    // Parse "rrggbb,rrggbb" as background then foreground
+    pub fn from_hex(text: &str) -> Option<Palette> {
+        let (bg, fg) = text.split_once(',')?;
+        Some(Palette {
+            bg: parse_color(bg.trim())?,
+            fg: parse_color(fg.trim())?,
+        })
+    }
+
+    // Grid lines are a slightly darker shade of the background
+    pub fn grid(&self) -> Color {
+        let darken = |c: u8| (c as u16 * 4 / 5) as u8;
+        Color::RGB(darken(self.bg.r), darken(self.bg.g), darken(self.bg.b))
+    }
+
+    // Mix between the background and foreground colours by pixel brightness
+    pub fn blend(&self, level: f32) -> Color {
+        let mix = |bg: u8, fg: u8| (bg as f32 + (fg as f32 - bg as f32) * level) as u8;
+        Color::RGB(
+            mix(self.bg.r, self.fg.r),
+            mix(self.bg.g, self.fg.g),
+            mix(self.bg.b, self.fg.b),
+        )
+    }
+}
+
+// VP-590 colour numbers have a bit each for red, blue and green
+pub fn vp590_color(index: u8) -> Color {
+    let level = |bit: u8| if index & bit != 0 { 255 } else { 0 };
+    Color::RGB(level(1), level(4), level(2))
+}
+
+fn parse_color(hex: &str) -> Option<Color> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some(Color::RGB((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
@@ -0,0 +1,106 @@
+use wasm_bindgen::prelude::*;
+
+use crate::cpu::Emu;
+use crate::cpu::Platform;
+
+// JavaScript-facing wrapper, built with `wasm-pack build --target web --no-default-features --features wasm`
+#[wasm_bindgen]
+pub struct WasmEmu {
+    emu: Emu,
+}
+
+impl Default for WasmEmu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl WasmEmu {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> WasmEmu {
+        WasmEmu { emu: Emu::new() }
+    }
+
+    pub fn reset(&mut self) {
+        self.emu.reset();
+    }
+
+    // Hi-res ROMs are recognised here, so check the screen size after loading
+    pub fn load(&mut self, data: &[u8]) -> Result<(), String> {
+        self.emu.set_platform(Platform::detect(data));
+        self.emu.load(data).map_err(|e| e.to_string())
+    }
+
+    pub fn tick(&mut self) {
+        self.emu.tick();
+    }
+
+    // Faster than calling tick count times, for running ahead without drawing
+    pub fn run_ticks(&mut self, count: u32) {
+        self.emu.run_ticks(count);
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.emu.tick_timers();
+    }
+
+    // Once set_vip_timing is on, call this once a frame instead of tick and tick_timers
+    pub fn run_frame(&mut self) -> u32 {
+        self.emu.run_frame()
+    }
+
+    pub fn keypress(&mut self, index: usize, pressed: bool) {
+        self.emu.keypress(index, pressed);
+    }
+
+    pub fn set_cosmac(&mut self) {
+        self.emu.set_cosmac();
+    }
+
+    pub fn set_vip_timing(&mut self) {
+        self.emu.set_vip_timing();
+    }
+
+    pub fn set_seed(&mut self, seed: u32) {
+        self.emu.set_seed(seed as u64);
+    }
+
+    pub fn set_stack_depth(&mut self, depth: usize) {
+        self.emu.set_stack_depth(depth);
+    }
+
+    // Why the machine stopped, if it crashed
+    pub fn fault(&self) -> Option<String> {
+        self.emu.get_fault().map(|fault| fault.to_string())
+    }
+
+    pub fn width(&self) -> usize {
+        self.emu.get_screen_size().0
+    }
+
+    pub fn height(&self) -> usize {
+        self.emu.get_screen_size().1
+    }
+
+    // Whether the screen needs repainting since the last call
+    pub fn take_dirty(&mut self) -> bool {
+        self.emu.take_dirty().is_some()
+    }
+
+    // Location of the framebuffer in wasm memory, a u64 per 64 pixels of a row with the
+    // leftmost in the top bit
+    pub fn display_ptr(&self) -> *const u64 {
+        self.emu.get_packed_display().as_ptr()
+    }
+
+    // Copy of the framebuffer for hosts that don't want to touch wasm memory
+    pub fn display(&self) -> Vec<u8> {
+        self.emu.get_display().iter().map(|pixel| *pixel as u8).collect()
+    }
+
+    // MegaChip's colour screen as 0xRRGGBB, to draw instead of display while it's on
+    pub fn frame(&self) -> Option<Vec<u32>> {
+        self.emu.get_frame().map(|frame| frame.to_vec())
+    }
+}
@@ -0,0 +1,72 @@
+// Programs and setup shared by the tests and benchmarks, which include this file with #[path]
+// so each of them only uses some of it
+#![allow(dead_code)]
+
+use yachip8emu::cpu::{Emu, Platform};
+
+// Arithmetic, skips, a call, a BCD write into data and a sprite each time round
+pub const PROGRAM: &str = "
+        LD V5, 0
+loop:   LD V0, V5
+        ADD V0, 7
+        LD V1, 3
+        XOR V0, V1
+        SUB V0, V1
+        OR V0, V1
+        SE V0, 0
+        ADD V5, 1
+        LD I, digits
+        LD B, V5
+        LD F, V0
+        DRW V5, V1, 5
+        CALL count
+        SNE V5, 0xFF
+        LD V5, 0
+        JP loop
+count:  ADD V6, 1
+        RET
+digits: DB 0, 0, 0
+";
+
+// The same without drawing, where decoding is most of the work
+pub const ARITHMETIC: &str = "
+        LD V5, 0
+loop:   LD V0, V5
+        ADD V0, 7
+        LD V1, 3
+        XOR V0, V1
+        SUB V0, V1
+        OR V0, V1
+        SE V0, 0
+        ADD V5, 1
+        LD I, digits
+        CALL count
+        SNE V5, 0xFF
+        LD V5, 0
+        JP loop
+count:  ADD V6, 1
+        RET
+digits: DB 0, 0, 0
+";
+
+// Rewrites what the ADD in its own loop adds every time round
+pub const SELF_MODIFYING: &str = "
+loop:   LD I, patch
+        ADD V0, 1
+        LD [I], V0
+        DB 0x71
+patch:  DB 1
+        ADD V2, V1
+        SUB V2, V0
+        LD DT, V2
+        LD V3, DT
+        JP loop
+";
+
+pub fn build(rom: &[u8], platform: Platform) -> Emu {
+    let mut emu = Emu::new();
+    emu.set_platform(platform);
+    emu.set_seed(1);
+    emu.load(rom).unwrap();
+    emu
+}
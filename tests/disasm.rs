@@ -0,0 +1,42 @@
+// Everything the disassembler prints has to assemble back to the same opcode on the same
+// platform, including each platform's own instructions.
+
+use yachip8emu::asm;
+use yachip8emu::cpu::Platform;
+use yachip8emu::disasm;
+
+fn round_trip(platform: Platform) {
+    for op in 0..=0xFFFF {
+        let text = disasm::disassemble(op, platform);
+        let rom = asm::assemble_for(&text, platform).unwrap_or_else(|e| panic!("{:04X} as {}: {}", op, text, e));
+        assert_eq!(rom, op.to_be_bytes(), "{:04X} as {} on {:?}", op, text, platform);
+    }
+}
+
+#[test]
+fn round_trips() {
+    for platform in [
+        Platform::Chip8,
+        Platform::Chip8X,
+        Platform::Chip8Hires,
+        Platform::MegaChip,
+        Platform::Eti660,
+        Platform::Dream6800,
+        Platform::Chip8E,
+    ] {
+        round_trip(platform);
+    }
+}
+
+#[test]
+fn labels_start_where_the_platform_loads() {
+    assert_eq!(asm::assemble_for("loop: JP loop", Platform::Eti660).unwrap(), [0x16, 0x00]);
+    assert_eq!(asm::assemble_for("loop: JP loop", Platform::Chip8X).unwrap(), [0x13, 0x00]);
+}
+
+#[test]
+fn platform_instructions_need_their_platform() {
+    assert_eq!(disasm::disassemble(0xF3F8, Platform::Chip8X), "OUT V3");
+    assert_eq!(disasm::disassemble(0xF303, Platform::Chip8E), "OUT V3");
+    assert!(asm::assemble("OUT V3").is_err());
+}
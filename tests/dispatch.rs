@@ -0,0 +1,125 @@
+// run_ticks has to do exactly what calling tick that many times does, including when the
+// instructions it has already decoded get written over.
+
+mod common;
+
+use yachip8emu::asm;
+use yachip8emu::cheat::CheatList;
+use yachip8emu::cpu::{Emu, Platform};
+
+use common::{build, ARITHMETIC, PROGRAM, SELF_MODIFYING};
+
+// Run both machines on by the same number of instructions, in uneven chunks so run_ticks
+// keeps reusing what it decoded in earlier calls, and check they end up the same
+fn run_both(ticked: &mut Emu, cached: &mut Emu, ticks: u32) {
+    let mut left = ticks;
+    let mut chunk = 1;
+    while left > 0 {
+        let count = chunk.min(left);
+        for _ in 0..count {
+            ticked.tick();
+        }
+        cached.run_ticks(count);
+        left -= count;
+        chunk = chunk % 13 + 1;
+    }
+    assert!(ticked.save_state() == cached.save_state(), "run_ticks went wrong within {} instructions", ticks);
+    assert_eq!(ticked.get_fault(), cached.get_fault());
+}
+
+fn check(source: &str, platform: Platform, ticks: u32) {
+    let rom = asm::assemble(source).unwrap();
+    run_both(&mut build(&rom, platform), &mut build(&rom, platform), ticks);
+}
+
+#[test]
+fn programs() {
+    check(PROGRAM, Platform::Chip8, 10_000);
+    check(ARITHMETIC, Platform::Chip8, 10_000);
+    check(SELF_MODIFYING, Platform::Chip8, 10_000);
+}
+
+// V0 is stored over the operand of the ADD after it
+#[test]
+fn fx55_rewrites_code() {
+    let source = "
+loop:   ADD V0, 3
+        LD I, patch
+        LD [I], V0
+        DB 0x72
+patch:  DB 0
+        JP loop
+";
+    check(source, Platform::Chip8, 1000);
+}
+
+// The hundreds digit of V0 is the low byte of the jump at patch, which alternates between
+// 0x200 and 0x202. The other two digits land after it, where nothing runs
+#[test]
+fn fx33_rewrites_code() {
+    let source = "
+        JP setup
+        ADD V4, 1
+        JP main
+setup:  LD V1, 200
+main:   XOR V0, V1
+        ADD V5, 1
+        LD I, digits
+        LD B, V0
+        JP patch
+patch:  DB 0x12
+digits: DB 0, 0, 0
+";
+    check(source, Platform::Chip8, 1000);
+}
+
+// 5XY2 stores V0 and V1 over the ADD like FX55 would, but leaves I alone
+#[test]
+fn chip8e_5xy2_rewrites_code() {
+    let source = "
+        LD V0, 0x72
+        LD I, patch
+loop:   ADD V1, 5
+        DB 0x50, 0x12
+patch:  ADD V2, 0
+        JP loop
+";
+    check(source, Platform::Chip8E, 1000);
+}
+
+// A frozen cheat keeps writing the ADD's operand back, undoing what the program changes it to
+#[test]
+fn cheat_rewrites_code() {
+    let rom = asm::assemble(SELF_MODIFYING).unwrap();
+    let patch = 0x200 + 7;
+    let mut ticked = build(&rom, Platform::Chip8);
+    let mut cached = build(&rom, Platform::Chip8);
+
+    let mut cheats = CheatList::default();
+    run_both(&mut ticked, &mut cached, 100);
+    for value in [5, 0x10, 0xFF] {
+        cheats.freeze(patch, value);
+        for _ in 0..20 {
+            cheats.apply(&mut ticked);
+            cheats.apply(&mut cached);
+            run_both(&mut ticked, &mut cached, 17);
+        }
+    }
+}
+
+// Loading a state replaces the code that was decoded with a different program
+#[test]
+fn load_state_replaces_code() {
+    let program = asm::assemble(PROGRAM).unwrap();
+    let arithmetic = asm::assemble(ARITHMETIC).unwrap();
+    let mut ticked = build(&program, Platform::Chip8);
+    let mut cached = build(&program, Platform::Chip8);
+    run_both(&mut ticked, &mut cached, 1000);
+
+    let mut other = build(&arithmetic, Platform::Chip8);
+    other.run_ticks(100);
+    let state = other.save_state();
+    ticked.load_state(&state).unwrap();
+    cached.load_state(&state).unwrap();
+    run_both(&mut ticked, &mut cached, 1000);
+}
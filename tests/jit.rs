@@ -0,0 +1,160 @@
+// run_jit has to leave the machine in exactly the state run_ticks does. Every program here
+// and a batch of random ones run both ways, a few instructions at a time, and are compared
+// after every step.
+#![cfg(feature = "jit")]
+
+mod common;
+
+use std::panic::{self, AssertUnwindSafe};
+
+use rand::rngs::SmallRng;
+use rand::Rng;
+use rand::SeedableRng;
+
+use yachip8emu::asm;
+use yachip8emu::cpu::{Emu, Platform};
+
+use common::{build, ARITHMETIC, PROGRAM, SELF_MODIFYING};
+
+// Per platform. Tests aren't optimised, and every machine sets up Cranelift for itself
+const RANDOM_PROGRAMS: usize = 64;
+
+// Jumps and I only go to the program itself or the last page. I landing on code makes the
+// memory writes self-modifying some of the time, which can leave instructions that fault
+// or panic, and a few that fault are put in to start with
+fn random_program(rng: &mut SmallRng) -> Vec<u8> {
+    let len = rng.gen_range(8..128);
+    let mut rom = Vec::new();
+    for _ in 0..len {
+        let x = rng.gen_range(0..0x10u16);
+        let y = rng.gen_range(0..0x10u16);
+        let nn = rng.gen::<u8>() as u16;
+        let target = 0x200 + 2 * rng.gen_range(0..len);
+        let op = match rng.gen_range(0..23) {
+            0 => 0x1000 | target,
+            1 => 0x2000 | target,
+            2 => 0x00EE,
+            3 => 0x3000 | x << 8 | nn,
+            4 => 0x4000 | x << 8 | nn,
+            5 => 0x5000 | x << 8 | y << 4,
+            6 => 0x9000 | x << 8 | y << 4,
+            7 => 0x6000 | x << 8 | nn,
+            8 => 0x7000 | x << 8 | nn,
+            9 => 0x8000 | x << 8 | y << 4 | [0, 1, 2, 3, 4, 5, 6, 7, 0xE][rng.gen_range(0..9)],
+            10 => 0xA000 | target,
+            11 => 0xAF00 | nn & 0xF0,
+            12 => 0xC000 | x << 8 | nn,
+            13 => 0xD000 | x << 8 | y << 4 | rng.gen_range(0..16),
+            14 => 0x00E0,
+            15 => 0xF007 | x << 8,
+            16 => 0xF015 | x << 8,
+            17 => 0xF018 | x << 8,
+            18 => 0xF029 | x << 8,
+            19 => 0xF033 | x << 8,
+            20 => 0xF055 | rng.gen_range(0..4) << 8,
+            // A key skip now and then, but mostly an instruction that doesn't exist
+            21 => 0xE000 | x << 8 | nn,
+            _ => 0xF065 | x << 8,
+        };
+        rom.extend_from_slice(&op.to_be_bytes());
+    }
+    // Go round again rather than run off the end
+    rom.extend_from_slice(&[0x12, 0x00]);
+    rom
+}
+
+// Only the first 4K of memory, which is as far as the programs here reach. The whole state
+// is compared at the end, since MegaChip's is 16MB
+fn same(a: &Emu, b: &Emu) -> bool {
+    a.get_v_reg() == b.get_v_reg()
+        && a.get_i_reg() == b.get_i_reg()
+        && a.get_pc() == b.get_pc()
+        && a.get_stack() == b.get_stack()
+        && a.get_timers() == b.get_timers()
+        && a.get_fault() == b.get_fault()
+        && a.get_packed_display() == b.get_packed_display()
+        && a.get_mem()[..0x1000] == b.get_mem()[..0x1000]
+}
+
+// Run rom both ways in steps of a few instructions, with the timers counting down in between,
+// and panic as soon as they disagree. A step that panics, such as EX9E with VX past the last
+// key, has to panic both ways, and ends the check since where it stopped can differ
+fn check(rom: &[u8], platform: Platform, steps: usize, rng: &mut SmallRng) {
+    let mut interpreted = build(rom, platform);
+    let mut compiled = build(rom, platform);
+
+    for step in 0..steps {
+        let ticks = rng.gen_range(1..200);
+        let interpreted_ok = panic::catch_unwind(AssertUnwindSafe(|| {
+            for _ in 0..ticks {
+                interpreted.tick();
+            }
+        }))
+        .is_ok();
+        let compiled_ok = panic::catch_unwind(AssertUnwindSafe(|| compiled.run_jit(ticks))).is_ok();
+        if interpreted_ok != compiled_ok {
+            panic!("only one way panicked at step {} of {:02X?} on {:?}", step, rom, platform);
+        }
+        if !interpreted_ok {
+            return;
+        }
+
+        interpreted.tick_timers();
+        compiled.tick_timers();
+
+        if !same(&interpreted, &compiled) {
+            panic!("compiled code went wrong after step {} of {:02X?} on {:?}", step, rom, platform);
+        }
+    }
+    if interpreted.save_state() != compiled.save_state() {
+        panic!("compiled code went wrong by the end of {:02X?} on {:?}", rom, platform);
+    }
+}
+
+fn check_source(source: &str) {
+    check(&asm::assemble(source).unwrap(), Platform::Chip8, 500, &mut SmallRng::seed_from_u64(1));
+}
+
+#[test]
+fn program() {
+    check_source(PROGRAM);
+}
+
+#[test]
+fn arithmetic() {
+    check_source(ARITHMETIC);
+}
+
+// Its block keeps being thrown away until it's left to the interpreter
+#[test]
+fn self_modifying() {
+    check_source(SELF_MODIFYING);
+}
+
+fn check_random(platform: Platform) {
+    let mut rng = SmallRng::seed_from_u64(1);
+    for _ in 0..RANDOM_PROGRAMS {
+        let rom = random_program(&mut rng);
+        check(&rom, platform, 50, &mut rng);
+    }
+}
+
+#[test]
+fn random_chip8() {
+    check_random(Platform::Chip8);
+}
+
+#[test]
+fn random_chip8e() {
+    check_random(Platform::Chip8E);
+}
+
+#[test]
+fn random_dream6800() {
+    check_random(Platform::Dream6800);
+}
+
+#[test]
+fn random_megachip() {
+    check_random(Platform::MegaChip);
+}
@@ -0,0 +1,34 @@
+// 0NNN machine code calls on the COSMAC VIP, which hand back to CHIP-8 with SEP R4.
+
+mod common;
+
+use yachip8emu::asm;
+use yachip8emu::cpu::Platform;
+
+use common::build;
+
+#[test]
+fn sep_r4_returns_to_chip8() {
+    // The routine adds 0x10 to V3 where the interpreter keeps it at 0xEF3, and points I at
+    // 0x345 through RA
+    let source = "
+            LD V3, 7
+            SYS code
+            ADD V3, 1
+    end:    JP end
+    code:   DB 0xF8, 0x0E, 0xBF, 0xF8, 0xF3, 0xAF
+            DB 0x0F, 0xFC, 0x10, 0x5F
+            DB 0xF8, 0x03, 0xBA, 0xF8, 0x45, 0xAA
+            DB 0xD4
+    ";
+    let rom = asm::assemble(source).unwrap();
+    let mut emu = build(&rom, Platform::Chip8);
+    emu.set_cosmac();
+    for _ in 0..3 {
+        emu.tick();
+    }
+    assert_eq!(emu.get_fault(), None);
+    assert_eq!(emu.get_v_reg()[3], 0x18);
+    assert_eq!(emu.get_i_reg(), 0x345);
+    assert_eq!(emu.get_pc(), 0x206);
+}
@@ -0,0 +1,112 @@
+// Save states and the stack depth each platform starts with.
+
+mod common;
+
+use yachip8emu::asm;
+use yachip8emu::cpu::{Emu, Fault, Platform, StateError};
+
+use common::build;
+
+// Calls itself until the stack overflows
+const RECURSE: &str = "deeper: CALL deeper";
+
+// Hi-res programs run the bootstrap first, so this counts what's on the stack instead of ticks
+fn calls_before_overflow(emu: &mut Emu) -> usize {
+    while emu.get_fault().is_none() {
+        emu.tick();
+    }
+    assert!(matches!(emu.get_fault(), Some(Fault::StackOverflow { .. })));
+    emu.get_stack().len()
+}
+
+#[test]
+fn platforms_have_their_own_stack_depth() {
+    for (platform, depth) in [
+        (Platform::Chip8, 16),
+        (Platform::Chip8X, 12),
+        (Platform::Chip8Hires, 12),
+        (Platform::Chip8E, 12),
+        (Platform::Dream6800, 16),
+    ] {
+        let rom = asm::assemble_for(RECURSE, platform).unwrap();
+        let mut emu = build(&rom, platform);
+        assert_eq!(emu.get_stack_depth(), depth, "{:?}", platform);
+        assert_eq!(calls_before_overflow(&mut emu), depth, "{:?}", platform);
+    }
+}
+
+#[test]
+fn set_stack_depth_outlasts_platform_changes() {
+    let mut emu = Emu::new();
+    emu.set_stack_depth(5);
+    emu.set_platform(Platform::Chip8X);
+    assert_eq!(emu.get_stack_depth(), 5);
+    emu.set_platform(Platform::Chip8);
+    assert_eq!(emu.get_stack_depth(), 5);
+}
+
+#[test]
+fn load_state_rejects_a_stack_deeper_than_allowed() {
+    let rom = asm::assemble(RECURSE).unwrap();
+    let mut deep = build(&rom, Platform::Chip8);
+    for _ in 0..14 {
+        deep.tick();
+    }
+    let state = deep.save_state();
+
+    let mut shallow = build(&rom, Platform::Chip8);
+    shallow.set_stack_depth(12);
+    let before = shallow.save_state();
+    assert_eq!(shallow.load_state(&state), Err(StateError::Corrupt));
+    assert!(shallow.save_state() == before, "a rejected state was partly loaded");
+
+    shallow.set_stack_depth(16);
+    shallow.load_state(&state).unwrap();
+    assert_eq!(shallow.get_stack().len(), 14);
+}
+
+// Run both machines the same way and check they stay the same
+fn run_both(a: &mut Emu, b: &mut Emu, frames: u32) {
+    for _ in 0..frames {
+        a.run_ticks(10);
+        b.run_ticks(10);
+        a.tick_timers();
+        b.tick_timers();
+        assert!(a.save_state() == b.save_state());
+    }
+}
+
+#[test]
+fn state_keeps_input_and_waits() {
+    let source = "
+            LD V0, 3
+            WAIT DT, V0
+            IN V1
+            ADD V2, 1
+    ";
+    let rom = asm::assemble_for(source, Platform::Chip8E).unwrap();
+    let mut emu = build(&rom, Platform::Chip8E);
+    emu.tick();
+    emu.tick();
+    emu.set_port_in(0x42);
+    emu.keypress2(3, true);
+
+    let mut loaded = build(&rom, Platform::Chip8E);
+    loaded.load_state(&emu.save_state()).unwrap();
+    assert!(loaded.save_state() == emu.save_state());
+    run_both(&mut emu, &mut loaded, 10);
+    assert_eq!(loaded.get_v_reg()[1], 0x42);
+}
+
+#[test]
+fn load_state_rejects_a_pc_outside_memory() {
+    let rom = asm::assemble(RECURSE).unwrap();
+    let mut emu = build(&rom, Platform::Chip8);
+    let mut state = emu.save_state();
+    let before = state.clone();
+    // The program counter follows the version, memory and the screen
+    let pc = 1 + 4096 + 64 * 32;
+    state[pc..pc + 2].copy_from_slice(&0x0FFFu16.to_be_bytes());
+    assert_eq!(emu.load_state(&state), Err(StateError::Corrupt));
+    assert!(emu.save_state() == before);
+}